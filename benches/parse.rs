@@ -0,0 +1,32 @@
+// Times parsing a 100,000 line document, most of whose lines carry a comment. Run with
+// `cargo bench --bench parse`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use calc::parse_document;
+
+const RUNS: usize = 10;
+
+// 5,000 sections of 20 lines: values, operations and a labelled subtotal, all commented.
+fn document() -> String {
+    let section = format!(
+        "100 rent, paid on the first\n{}[2, 3] / 2 bus fares, give or take\n--- week:\n\n",
+        "1.5 * 2 food, for lunch and dinner\n".repeat(16)
+    );
+    section.repeat(5_000)
+}
+
+fn main() {
+    let src = document();
+    assert_eq!(src.lines().count(), 100_000);
+
+    let mut fastest = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let lines = parse_document(black_box(&src)).expect("the document parses");
+        fastest = fastest.min(start.elapsed());
+        black_box(lines);
+    }
+    println!("parsed 100,000 lines in {fastest:?} (fastest of {RUNS} runs)");
+}
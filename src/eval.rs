@@ -0,0 +1,648 @@
+use std::{collections::HashMap, fmt};
+
+use chumsky::{error::Rich, span::SimpleSpan};
+use rust_decimal::{prelude::ToPrimitive, Decimal};
+
+use crate::{parse_document, Function, Line, Operation, Spanned, SubtotalKind, Value};
+
+#[derive(Debug)]
+pub enum EvalError {
+    DivisionByZero,
+    DivisorContainsZero,
+    NonIntegerExponent,
+    /// An operation with no meaningful result, like `0 / 0`, given as written, along with where its
+    /// operands were written when they were.
+    Undefined { form: &'static str, left: Option<SimpleSpan>, right: Option<SimpleSpan> },
+    Unbounded,
+    NegativeSqrt,
+    EmptyIntersection,
+    NotANumber,
+    InvalidPrecision,
+    IntegerDivisionOfInterval,
+    UndefinedVariable(String),
+    NoSubtotal,
+    SubtotalMismatch { written: Value, computed: Value },
+    OffTarget { target: Value, computed: Value },
+    /// A result too large for a decimal.
+    Overflow,
+    /// A call with a number of arguments the function doesn't take, which the parser rules out.
+    ArgumentCount { function: Function, given: usize },
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::DivisorContainsZero => write!(f, "divisor interval contains zero"),
+            EvalError::NonIntegerExponent => write!(f, "exponent must be an integer"),
+            EvalError::Undefined { form, .. } => write!(f, "undefined result `{form}`"),
+            EvalError::Unbounded => write!(f, "interval is unbounded"),
+            EvalError::NegativeSqrt => write!(f, "square root of a negative number"),
+            EvalError::EmptyIntersection => write!(f, "intervals don't intersect"),
+            EvalError::IntegerDivisionOfInterval => {
+                write!(f, "`//` and `%` only work on numbers, not intervals")
+            }
+            EvalError::InvalidPrecision => {
+                write!(f, "number of decimal places must be a whole number from 0 to 28")
+            }
+            EvalError::NotANumber => write!(f, "comparison results can't be used as numbers"),
+            EvalError::Overflow => write!(f, "result is too large to represent"),
+            EvalError::ArgumentCount { function, given } => {
+                write!(f, "`{}` doesn't take {given} argument(s)", function.name())
+            }
+            EvalError::UndefinedVariable(name) => write!(f, "undefined variable `{name}`"),
+            EvalError::NoSubtotal => write!(f, "`@` used before any subtotal"),
+            EvalError::SubtotalMismatch { written, computed } => {
+                write!(f, "subtotal should be ")?;
+                write_value(f, *computed)?;
+                write!(f, " but ")?;
+                write_value(f, *written)?;
+                write!(f, " was written")
+            }
+            EvalError::OffTarget { target, computed } => {
+                write!(f, "subtotal ")?;
+                write_value(f, *computed)?;
+                write!(f, " isn't within its target ")?;
+                write_value(f, *target)
+            }
+        }
+    }
+}
+
+impl EvalError {
+    // An undefined result, not yet pointed at the operands it came from.
+    pub(crate) fn undefined(form: &'static str) -> EvalError {
+        EvalError::Undefined { form, left: None, right: None }
+    }
+
+    // Points an undefined result at the operands of `op`, the operation it came from.
+    fn at_operands(self, op: &Operation) -> EvalError {
+        match (self, op) {
+            (
+                EvalError::Undefined { form, .. },
+                Operation::Add(l, r, _)
+                | Operation::Sub(l, r, _)
+                | Operation::Div(l, r, _)
+                | Operation::FloorDiv(l, r, _)
+                | Operation::Mod(l, r, _),
+            ) => EvalError::Undefined { form, left: l.span(), right: r.span() },
+            (e, _) => e,
+        }
+    }
+}
+
+fn write_value(f: &mut fmt::Formatter<'_>, v: Value) -> fmt::Result {
+    match v {
+        Value::Number(n) => write!(f, "{}", n.normalize()),
+        Value::Interval(a, b, openness) => {
+            let open = if openness.lower { '(' } else { '[' };
+            let close = if openness.upper { ')' } else { ']' };
+            write!(f, "{open}{a}, {b}{close}")
+        }
+        Value::Truth(t) => write!(f, "{t}"),
+    }
+}
+
+/// What expressions can refer to besides literals.
+#[derive(Default)]
+struct Env {
+    vars: HashMap<String, Value>,
+    /// The value of the most recent subtotal, for `@`.
+    subtotal: Option<Value>,
+}
+
+// Evaluated with an explicit stack rather than by recursion, so that deeply nested expressions
+// can't overflow the call stack.
+//
+// Errors come with the span of the operation that failed, when it has one.
+fn evaluate_operation(
+    op: &Operation,
+    env: &Env,
+) -> Result<Value, (EvalError, Option<SimpleSpan>)> {
+    enum Task<'a> {
+        /// Evaluate an operation, scheduling its operands first.
+        Visit(&'a Operation),
+        /// Combine the already evaluated operands of an operation, found on top of `values`.
+        Apply(&'a Operation),
+    }
+
+    let mut tasks = vec![Task::Visit(op)];
+    let mut values = Vec::new();
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(op) => {
+                tasks.push(Task::Apply(op));
+                // Pushed in reverse, so that operands are evaluated left to right.
+                match op {
+                    Operation::Add(l, r, _)
+                    | Operation::Sub(l, r, _)
+                    | Operation::Mul(l, r, _)
+                    | Operation::Div(l, r, _)
+                    | Operation::FloorDiv(l, r, _)
+                    | Operation::Mod(l, r, _)
+                    | Operation::Pow(l, r, _)
+                    | Operation::Union(l, r, _)
+                    | Operation::Intersect(l, r, _)
+                    | Operation::Less(l, r, _)
+                    | Operation::Greater(l, r, _) => {
+                        tasks.push(Task::Visit(r));
+                        tasks.push(Task::Visit(l));
+                    }
+                    Operation::Neg(r) => tasks.push(Task::Visit(r)),
+                    Operation::Call { args, .. } => {
+                        tasks.extend(args.iter().rev().map(Task::Visit));
+                    }
+                    Operation::Var(_) | Operation::LastSubtotal | Operation::Value(..) => {}
+                }
+            }
+            Task::Apply(op) => {
+                let v = apply_operation(op, &mut values, env)
+                    .map_err(|e| (e.at_operands(op), op.span()))?;
+                values.push(v);
+            }
+        }
+    }
+    Ok(values.pop().expect("an operation evaluates to a value"))
+}
+
+const OPERAND: &str = "operands are evaluated before their operation";
+
+// Operands are always numbers or intervals: comparison results only ever make it to the output.
+fn numeric(v: Value) -> Result<Value, EvalError> {
+    match v {
+        Value::Truth(_) => Err(EvalError::NotANumber),
+        v => Ok(v),
+    }
+}
+
+fn pop_pair(values: &mut Vec<Value>) -> Result<(Value, Value), EvalError> {
+    let r = numeric(values.pop().expect(OPERAND))?;
+    let l = numeric(values.pop().expect(OPERAND))?;
+    Ok((l, r))
+}
+
+// The number of decimal places asked for by the precision argument of a rounding function.
+fn decimal_places(v: Value) -> Result<u32, EvalError> {
+    match v {
+        Value::Number(n) if n.fract().is_zero() => n.to_u32().filter(|&dp| dp <= 28),
+        _ => None,
+    }
+    .ok_or(EvalError::InvalidPrecision)
+}
+
+// Evaluates `op` given the values of its operands, which are popped off `values`.
+fn apply_operation(op: &Operation, values: &mut Vec<Value>, env: &Env) -> Result<Value, EvalError> {
+    match op {
+        Operation::Add(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.add(r)
+        }
+        Operation::Sub(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.sub(r)
+        }
+        Operation::Mul(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.mul(r)
+        }
+        Operation::Div(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.div(r)
+        }
+        Operation::FloorDiv(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.floor_div(r)
+        }
+        Operation::Mod(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.rem(r)
+        }
+        Operation::Pow(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.pow(r)
+        }
+        Operation::Union(..) => {
+            let (l, r) = pop_pair(values)?;
+            Ok(l.union(r))
+        }
+        Operation::Intersect(..) => {
+            let (l, r) = pop_pair(values)?;
+            l.intersect(r)
+        }
+        Operation::Less(..) => {
+            let (l, r) = pop_pair(values)?;
+            Ok(l.less(r))
+        }
+        Operation::Greater(..) => {
+            let (l, r) = pop_pair(values)?;
+            Ok(r.less(l))
+        }
+        Operation::Neg(_) => Ok(numeric(values.pop().expect(OPERAND))?.neg()),
+        Operation::Call { name, args } => {
+            let given = args.len();
+            let args = values.split_off(values.len() - given);
+            let args = args
+                .into_iter()
+                .map(numeric)
+                .collect::<Result<Vec<_>, _>>()?;
+            match (name, args.as_slice()) {
+                (Function::Mid | Function::Point, [x]) => x.mid(),
+                (Function::Radius, [x]) => x.radius(),
+                (Function::Abs, [x]) => Ok(x.abs()),
+                (Function::Min, [x, y]) => Ok(x.min(*y)),
+                (Function::Max, [x, y]) => Ok(x.max(*y)),
+                (Function::Sq, [x]) => x.pow(Value::Number(2.into())),
+                (Function::Sqrt, [x]) => x.sqrt(),
+                (Function::Recip, [x]) => x.recip(),
+                (Function::Contains, [x, y]) => Ok(x.contains(*y)),
+                (Function::Round, [x]) => Ok(x.round(0)),
+                (Function::Round, [x, dp]) => Ok(x.round(decimal_places(*dp)?)),
+                (Function::Floor, [x]) => Ok(x.floor(0)),
+                (Function::Floor, [x, dp]) => Ok(x.floor(decimal_places(*dp)?)),
+                (Function::Ceil, [x]) => Ok(x.ceil(0)),
+                (Function::Ceil, [x, dp]) => Ok(x.ceil(decimal_places(*dp)?)),
+                _ => Err(EvalError::ArgumentCount {
+                    function: *name,
+                    given,
+                }),
+            }
+        }
+        Operation::Var(name) => env
+            .vars
+            .get(name)
+            .copied()
+            .ok_or_else(|| EvalError::UndefinedVariable(name.clone())),
+        Operation::LastSubtotal => env.subtotal.ok_or(EvalError::NoSubtotal),
+        Operation::Value(v, _) => Ok(*v),
+    }
+}
+
+/// Knobs controlling how [`evaluate_with`] accumulates a document.
+#[derive(Debug, Clone, Default)]
+pub struct EvalOptions {
+    /// Start a fresh running total after every subtotal instead of carrying it over.
+    pub sections: bool,
+    /// Add every operation to the running total instead of subtracting it from the first one.
+    pub sum: bool,
+}
+
+/// One update of the running total, as recorded by [`evaluate_traced`] and on each operation line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Step {
+    /// The running total starts from a value, at the top of the document or of a section.
+    Start(Value),
+    /// A value is added to the running total, giving `total`.
+    Add { value: Value, total: Value },
+    /// A value is subtracted from the running total, giving `total`.
+    Sub { value: Value, total: Value },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (sign, value, total) = match self {
+            Step::Start(v) => {
+                write!(f, "start ")?;
+                return write_value(f, *v);
+            }
+            Step::Add { value, total } => ('+', value, total),
+            Step::Sub { value, total } => ('-', value, total),
+        };
+        write!(f, "{sign} ")?;
+        write_value(f, *value)?;
+        write!(f, " = ")?;
+        write_value(f, *total)
+    }
+}
+
+/// Fills in the value of every subtotal line from the operations preceding it.
+pub fn evaluate(lines: &mut [Line]) -> Result<(), Spanned<EvalError>> {
+    evaluate_with(lines, &EvalOptions::default()).map(|_| ())
+}
+
+/// Like [`evaluate`], but takes the document by value and hands back the evaluated one, leaving no
+/// half-evaluated document behind on errors.
+pub fn evaluated(mut lines: Vec<Line<'_>>) -> Result<Vec<Line<'_>>, Spanned<EvalError>> {
+    evaluate(&mut lines)?;
+    Ok(lines)
+}
+
+/// Like [`evaluate`], but with non-default accumulation semantics.
+///
+/// Returns the grand total of the document: the final running total, or with `sections` the sum of
+/// every section's total.
+pub fn evaluate_with(
+    lines: &mut [Line],
+    opts: &EvalOptions,
+) -> Result<Option<Value>, Spanned<EvalError>> {
+    evaluate_traced(lines, opts).map(|(total, _)| total)
+}
+
+/// Like [`evaluate_with`], but also returns every step taken by the running total, in order.
+pub fn evaluate_traced(
+    lines: &mut [Line],
+    opts: &EvalOptions,
+) -> Result<(Option<Value>, Vec<Step>), Spanned<EvalError>> {
+    let mut evaluator = Evaluator::new(opts.clone());
+    let mut trace = Vec::new();
+    for line in lines {
+        trace.extend(evaluator.line(line)?);
+    }
+    Ok((evaluator.finish()?, trace))
+}
+
+/// Evaluates a document one line at a time, for documents too large to hold all at once.
+///
+/// Feeding every line of a document to [`Evaluator::line`] in order and then calling
+/// [`Evaluator::finish`] gives the same results as [`evaluate_traced`].
+pub struct Evaluator {
+    opts: EvalOptions,
+    env: Env,
+    /// The running total.
+    accu: Option<Value>,
+    /// The sum of the totals of the sections ended so far, with `sections`.
+    total: Option<Value>,
+    /// Where the running total was last updated, to blame when combining totals fails.
+    last_span: SimpleSpan,
+    /// The values of the operations since the last subtotal, to average.
+    section: Vec<Spanned<Value>>,
+}
+
+impl Evaluator {
+    pub fn new(opts: EvalOptions) -> Evaluator {
+        Evaluator {
+            opts,
+            env: Env::default(),
+            accu: None,
+            total: None,
+            last_span: SimpleSpan::from(0..0),
+            section: Vec::new(),
+        }
+    }
+
+    /// Fills in the values of the next line of the document, returning the step it made the
+    /// running total take, if any.
+    pub fn line(&mut self, line: &mut Line) -> Result<Option<Step>, Spanned<EvalError>> {
+        match line {
+            Line::Operation {
+                operation,
+                value,
+                running,
+                step: entered,
+                span,
+                ..
+            } => {
+                let v = evaluate_operation(operation, &self.env)
+                    .map_err(|(e, at)| (e, at.unwrap_or(*span)))?;
+                *value = Some(v);
+                // A comparison is shown for its own sake, and has no amount to add to the total.
+                if let Value::Truth(_) = v {
+                    *entered = None;
+                    return Ok(None);
+                }
+                self.section.push((v, *span));
+                let (next, step) = match self.accu {
+                    Some(accu) if self.opts.sum => {
+                        let total = accu.add(v).map_err(|e| (e, *span))?;
+                        (total, Step::Add { value: v, total })
+                    }
+                    Some(accu) => {
+                        let total = accu.sub(v).map_err(|e| (e, *span))?;
+                        (total, Step::Sub { value: v, total })
+                    }
+                    None => (v, Step::Start(v)),
+                };
+                self.accu = Some(next);
+                *running = self.accu;
+                *entered = Some(step);
+                self.last_span = *span;
+                return Ok(Some(step));
+            }
+            Line::Subtotal {
+                kind,
+                label,
+                value,
+                target,
+                on_target,
+                ..
+            } => {
+                match kind {
+                    SubtotalKind::Total => {
+                        if let Some(accu) = self.accu {
+                            *value = Some(accu);
+                        }
+                    }
+                    SubtotalKind::Average => {
+                        if let Some(mean) = mean(&self.section)? {
+                            *value = Some(mean);
+                        }
+                    }
+                    SubtotalKind::Hull => {
+                        let hull = self.section.iter().map(|&(v, _)| v).reduce(Value::union);
+                        if let Some(hull) = hull {
+                            *value = Some(hull);
+                        }
+                    }
+                }
+                if let (Some(target), Some(value)) = (target, *value) {
+                    *on_target = Some(target.encloses(value));
+                }
+                self.section.clear();
+                if let Some(v) = *value {
+                    self.env.subtotal = Some(v);
+                    if let Some(label) = label {
+                        self.env.vars.insert(label.clone(), v);
+                    }
+                }
+                if self.opts.sections {
+                    self.total = add_totals(self.total, self.accu.take(), self.last_span)?;
+                }
+            }
+            Line::Assign {
+                name,
+                operation,
+                value,
+                span,
+                ..
+            } => {
+                let v = evaluate_operation(operation, &self.env)
+                    .map_err(|(e, at)| (e, at.unwrap_or(*span)))?;
+                *value = Some(v);
+                self.env.vars.insert(name.clone(), v);
+            }
+            Line::Comment(..) | Line::Blank(_) => {}
+        }
+        Ok(None)
+    }
+
+    /// The names of the variables defined so far, by assignments and subtotal labels.
+    pub fn variables(&self) -> impl Iterator<Item = &str> {
+        self.env.vars.keys().map(String::as_str)
+    }
+
+    /// The grand total of the document: the final running total, or with `sections` the sum of
+    /// every section's total.
+    pub fn finish(self) -> Result<Option<Value>, Spanned<EvalError>> {
+        add_totals(self.total, self.accu, self.last_span)
+    }
+}
+
+/// Which kind of [`Line`] an [`EvaluatedLine`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Operation,
+    Subtotal(SubtotalKind),
+    Assign,
+    Comment,
+    Blank,
+}
+
+/// A line of a document along with the values [`evaluate_iter`] filled in.
+#[derive(Debug)]
+pub struct EvaluatedLine<'a> {
+    pub line: Line<'a>,
+    /// The step the line made the running total take, if any.
+    pub step: Option<Step>,
+}
+
+impl<'a> EvaluatedLine<'a> {
+    pub fn kind(&self) -> LineKind {
+        match &self.line {
+            Line::Operation { .. } => LineKind::Operation,
+            Line::Subtotal { kind, .. } => LineKind::Subtotal(*kind),
+            Line::Assign { .. } => LineKind::Assign,
+            Line::Comment(..) => LineKind::Comment,
+            Line::Blank(_) => LineKind::Blank,
+        }
+    }
+
+    /// The value of an operation, subtotal or definition. Subtotals with nothing above them have
+    /// none.
+    pub fn value(&self) -> Option<Value> {
+        match &self.line {
+            Line::Operation { value, .. }
+            | Line::Subtotal { value, .. }
+            | Line::Assign { value, .. } => *value,
+            Line::Comment(..) | Line::Blank(_) => None,
+        }
+    }
+
+    /// The comment at the end of the line, or the whole of a standalone comment.
+    pub fn comment(&self) -> &str {
+        match &self.line {
+            Line::Operation { comment, .. }
+            | Line::Subtotal { comment, .. }
+            | Line::Assign { comment, .. }
+            | Line::Comment(comment, _) => comment,
+            Line::Blank(_) => "",
+        }
+    }
+}
+
+impl<'a> From<EvaluatedLine<'a>> for Line<'a> {
+    fn from(evaluated: EvaluatedLine<'a>) -> Line<'a> {
+        evaluated.line
+    }
+}
+
+/// Parses `src` and evaluates it lazily, one line per item, stopping after the first error.
+///
+/// The whole document is parsed up front, so syntax errors are reported before any line is
+/// evaluated. Collecting the lines gives the same document as [`evaluate`].
+pub fn evaluate_iter(
+    src: &str,
+) -> Result<
+    impl Iterator<Item = Result<EvaluatedLine<'_>, Spanned<EvalError>>>,
+    Vec<Rich<'_, char>>,
+> {
+    let mut lines = parse_document(src)?.into_iter();
+    let mut evaluator = Some(Evaluator::new(EvalOptions::default()));
+    Ok(std::iter::from_fn(move || {
+        let mut line = lines.next()?;
+        match evaluator.as_mut()?.line(&mut line) {
+            Ok(step) => Some(Ok(EvaluatedLine { line, step })),
+            Err(e) => {
+                evaluator = None;
+                Some(Err(e))
+            }
+        }
+    }))
+}
+
+/// Compares the subtotals written in the source of an evaluated document against the computed ones,
+/// and against their targets, returning an error for each one that disagrees.
+pub fn check(lines: &[Line]) -> Vec<Spanned<EvalError>> {
+    check_within(lines, Decimal::ZERO)
+}
+
+/// Like [`check`], but lets written subtotals be off from the computed ones by up to `epsilon`,
+/// which values derived from a division rarely match exactly.
+pub fn check_within(lines: &[Line], epsilon: Decimal) -> Vec<Spanned<EvalError>> {
+    let mut errors = Vec::new();
+    for line in lines {
+        let Line::Subtotal {
+            value: Some(computed),
+            written,
+            target,
+            on_target,
+            span,
+            ..
+        } = line
+        else {
+            continue;
+        };
+        if let Some(written) = written.filter(|written| !written.within(*computed, epsilon)) {
+            let computed = *computed;
+            errors.push((EvalError::SubtotalMismatch { written, computed }, *span));
+        }
+        if let (Some(target), Some(false)) = (target, on_target) {
+            let (target, computed) = (*target, *computed);
+            errors.push((EvalError::OffTarget { target, computed }, *span));
+        }
+    }
+    errors
+}
+
+/// Counts the operations of an evaluated document and adds up their values, regardless of how the
+/// running total combines them.
+pub fn summary(lines: &[Line]) -> Result<(usize, Option<Value>), Spanned<EvalError>> {
+    let mut count = 0;
+    let mut sum = None;
+    for line in lines {
+        if let Line::Operation {
+            value: Some(v),
+            span,
+            ..
+        } = line
+        {
+            if let Value::Truth(_) = v {
+                continue;
+            }
+            count += 1;
+            sum = add_totals(sum, Some(*v), *span)?;
+        }
+    }
+    Ok((count, sum))
+}
+
+// The mean of `values`, averaging the bounds of intervals, or `None` if there are none.
+fn mean(values: &[Spanned<Value>]) -> Result<Option<Value>, Spanned<EvalError>> {
+    let mut sum = None;
+    for &(v, span) in values {
+        sum = add_totals(sum, Some(v), span)?;
+    }
+    let count = Value::Number(values.len().into());
+    sum.map(|sum| sum.div(count).map_err(|e| (e, values[0].1)))
+        .transpose()
+}
+
+fn add_totals(
+    a: Option<Value>,
+    b: Option<Value>,
+    span: SimpleSpan,
+) -> Result<Option<Value>, Spanned<EvalError>> {
+    match (a, b) {
+        (Some(a), Some(b)) => a.add(b).map(Some).map_err(|e| (e, span)),
+        (a, b) => Ok(a.or(b)),
+    }
+}
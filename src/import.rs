@@ -0,0 +1,142 @@
+use std::fmt;
+
+use chumsky::span::SimpleSpan;
+use rust_decimal::Decimal;
+
+use crate::{Line, Operation, Spanned, SubtotalKind, Value};
+
+#[derive(Debug)]
+pub enum ImportError {
+    MissingColumn(String),
+    /// A row too short to have a cell in the column.
+    MissingCell { row: usize },
+    NotANumber { row: usize, cell: String },
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::MissingColumn(column) => write!(f, "no column named `{column}`"),
+            ImportError::MissingCell { row } => write!(f, "row {row} has no cell in the column"),
+            ImportError::NotANumber { row, cell } => {
+                write!(f, "row {row}: `{cell}` is not a number")
+            }
+        }
+    }
+}
+
+/// Builds a document out of the column named `column` of a CSV file: an operation per row, labelled
+/// with its row number, followed by a subtotal.
+///
+/// Rows are counted from 1, including the header. Empty cells are skipped, while rows too short to
+/// have a cell and cells that aren't numbers are errors, every one of which is returned.
+pub fn import_csv(
+    src: &str,
+    column: &str,
+) -> Result<Vec<Line<'static>>, Vec<Spanned<ImportError>>> {
+    let mut rows = records(src);
+    let Some((_, header)) = rows.next() else {
+        return Err(vec![(
+            ImportError::MissingColumn(column.to_string()),
+            SimpleSpan::from(0..0),
+        )]);
+    };
+    let index = header
+        .iter()
+        .position(|(name, _)| name.trim() == column)
+        .ok_or_else(|| {
+            // Rows always have at least one field.
+            let span = header[0].1.start..header[header.len() - 1].1.end;
+            vec![(ImportError::MissingColumn(column.to_string()), span.into())]
+        })?;
+
+    let mut lines = Vec::new();
+    let mut errors = Vec::new();
+    for (row, fields) in rows {
+        // Rows always have at least one field.
+        let row_span = SimpleSpan::from(fields[0].1.start..fields[fields.len() - 1].1.end);
+        let Some((cell, span)) = fields.into_iter().nth(index) else {
+            errors.push((ImportError::MissingCell { row }, row_span));
+            continue;
+        };
+        let cell = cell.trim();
+        if cell.is_empty() {
+            continue;
+        }
+
+        let parsed = cell
+            .parse::<Decimal>()
+            .or_else(|_| Decimal::from_scientific(cell));
+        let Ok(n) = parsed else {
+            let cell = cell.to_string();
+            errors.push((ImportError::NotANumber { row, cell }, span));
+            continue;
+        };
+        lines.push(Line::Operation {
+            operation: Operation::Value(Value::Number(n), span),
+            value: None,
+            running: None,
+            step: None,
+            comment: format!("row {row}").into(),
+            span,
+        });
+    }
+
+    lines.push(Line::Subtotal {
+        kind: SubtotalKind::Total,
+        label: None,
+        value: None,
+        written: None,
+        target: None,
+        on_target: None,
+        comment: column.to_string().into(),
+        span: SimpleSpan::from(src.len()..src.len()),
+    });
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(lines)
+}
+
+// Splits `src` into numbered rows of fields along with their spans, unquoting fields as needed.
+// Quoted fields can hold commas but not line breaks.
+fn records(src: &str) -> impl Iterator<Item = (usize, Vec<(String, SimpleSpan)>)> + '_ {
+    let mut offset = 0;
+    src.split('\n').enumerate().filter_map(move |(i, line)| {
+        let start = offset;
+        offset += line.len() + 1;
+
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some((i + 1, fields(line, start)))
+    })
+}
+
+fn fields(line: &str, start: usize) -> Vec<(String, SimpleSpan)> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut field_start = 0;
+    let mut quoted = false;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek().map(|&(_, c)| c) == Some('"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                fields.push((
+                    std::mem::take(&mut field),
+                    (start + field_start..start + i).into(),
+                ));
+                field_start = i + 1;
+            }
+            c => field.push(c),
+        }
+    }
+    fields.push((field, (start + field_start..start + line.len()).into()));
+    fields
+}
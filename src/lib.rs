@@ -0,0 +1,436 @@
+use std::{borrow::Cow, ops::RangeInclusive, str::FromStr};
+
+use chumsky::span::SimpleSpan;
+
+mod eval;
+mod import;
+mod parse;
+mod print;
+mod value;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use eval::{
+    check, check_within, evaluate, evaluate_iter, evaluate_traced, evaluate_with, evaluated, summary,
+    EvalError, EvalOptions, EvaluatedLine, Evaluator, LineKind, Step,
+};
+pub use import::{import_csv, ImportError};
+pub use parse::{parse_document, parse_document_after, parse_document_with};
+pub use print::{pretty_print, Align, CommentOverflow, Format, FormatOptions, FormatOptionsBuilder};
+pub use value::{Bound, Openness, Truth, Value};
+#[cfg(feature = "wasm")]
+pub use wasm::run;
+
+pub type Spanned<T> = (T, SimpleSpan);
+
+// Spans are stored as plain ranges, since chumsky's spans can't be serialized themselves.
+#[cfg(feature = "serde")]
+mod serde_span {
+    use std::ops::Range;
+
+    use chumsky::span::SimpleSpan;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(span: &SimpleSpan, s: S) -> Result<S::Ok, S::Error> {
+        span.into_range().serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<SimpleSpan, D::Error> {
+        Range::<usize>::deserialize(d).map(SimpleSpan::from)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Line<'a> {
+    Operation {
+        operation: Operation,
+        /// The evaluated operation, filled in by [`evaluate`].
+        value: Option<Value>,
+        /// The running total after this operation, filled in by [`evaluate`].
+        running: Option<Value>,
+        /// How the operation entered the running total, filled in by [`evaluate`].
+        #[cfg_attr(feature = "serde", serde(default))]
+        step: Option<Step>,
+        comment: Cow<'a, str>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))]
+        span: SimpleSpan,
+    },
+    Subtotal {
+        /// What the subtotal computes, as picked by its rule.
+        #[cfg_attr(feature = "serde", serde(default))]
+        kind: SubtotalKind,
+        /// The name written after the rule, as in `--- groceries:`, by which later lines can refer
+        /// to the subtotal.
+        #[cfg_attr(feature = "serde", serde(default))]
+        label: Option<String>,
+        value: Option<Value>,
+        /// The value written in the source, if any, kept around to [`check`] it.
+        written: Option<Value>,
+        /// What the value should come to, written after the rule as in `--- 100 ± 0.5`.
+        #[cfg_attr(feature = "serde", serde(default))]
+        target: Option<Value>,
+        /// Whether the value is within `target`, filled in by [`evaluate`].
+        #[cfg_attr(feature = "serde", serde(default))]
+        on_target: Option<bool>,
+        comment: Cow<'a, str>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))]
+        span: SimpleSpan,
+    },
+    /// A `name = expression` definition, which doesn't take part in the running total but can be
+    /// referred to by name in the lines after it.
+    Assign {
+        name: String,
+        operation: Operation,
+        /// The evaluated operation, filled in by [`evaluate`].
+        value: Option<Value>,
+        comment: Cow<'a, str>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))]
+        span: SimpleSpan,
+    },
+    /// A standalone `#` comment, kept verbatim along with its indentation.
+    Comment(
+        Cow<'a, str>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// An empty line, kept to space out the document.
+    Blank(#[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan),
+}
+
+/// An expression, as parsed. Values and binary operations remember where they were written, so
+/// that errors can point at them.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operation {
+    Add(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    Sub(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    Mul(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    Div(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// Division rounded down to a whole number.
+    FloorDiv(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// The remainder of [`Operation::FloorDiv`], with the sign of the divisor.
+    Mod(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    Pow(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// The smallest interval containing both operands.
+    Union(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// The interval shared by both operands.
+    Intersect(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// Whether the left operand is less than the right one, as a [`Truth`].
+    Less(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    /// Whether the left operand is greater than the right one, as a [`Truth`].
+    Greater(
+        Box<Operation>,
+        Box<Operation>,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+    Neg(Box<Operation>),
+    /// A call to one of the built-in [`Function`]s.
+    Call { name: Function, args: Vec<Operation> },
+    /// A reference to a variable defined by an earlier [`Line::Assign`].
+    Var(String),
+    /// `@`, the value of the most recent subtotal.
+    LastSubtotal,
+    Value(
+        Value,
+        #[cfg_attr(feature = "serde", serde(with = "serde_span"))] SimpleSpan,
+    ),
+}
+
+impl Operation {
+    /// Where the operation was written, if it remembers.
+    pub fn span(&self) -> Option<SimpleSpan> {
+        match self {
+            Operation::Add(.., span)
+            | Operation::Sub(.., span)
+            | Operation::Mul(.., span)
+            | Operation::Div(.., span)
+            | Operation::FloorDiv(.., span)
+            | Operation::Mod(.., span)
+            | Operation::Pow(.., span)
+            | Operation::Union(.., span)
+            | Operation::Intersect(.., span)
+            | Operation::Less(.., span)
+            | Operation::Greater(.., span)
+            | Operation::Value(_, span) => Some(*span),
+            Operation::Neg(_)
+            | Operation::Call { .. }
+            | Operation::Var(_)
+            | Operation::LastSubtotal => None,
+        }
+    }
+
+    // Moves the operands of this operation onto `stack`, leaving placeholders in their stead.
+    fn take_operands(&mut self, stack: &mut Vec<Operation>) {
+        let mut take = |op: &mut Operation| {
+            stack.push(std::mem::replace(op, Operation::LastSubtotal));
+        };
+        match self {
+            Operation::Add(l, r, _)
+            | Operation::Sub(l, r, _)
+            | Operation::Mul(l, r, _)
+            | Operation::Div(l, r, _)
+            | Operation::FloorDiv(l, r, _)
+            | Operation::Mod(l, r, _)
+            | Operation::Pow(l, r, _)
+            | Operation::Union(l, r, _)
+            | Operation::Intersect(l, r, _)
+            | Operation::Less(l, r, _)
+            | Operation::Greater(l, r, _) => {
+                take(l.as_mut());
+                take(r.as_mut());
+            }
+            Operation::Neg(r) => take(r.as_mut()),
+            Operation::Call { args, .. } => args.iter_mut().for_each(take),
+            Operation::Var(_) | Operation::LastSubtotal | Operation::Value(..) => {}
+        }
+    }
+}
+
+// Freed with an explicit stack, the way operations are evaluated, so that dropping a deeply nested
+// one can't overflow the call stack either.
+impl Drop for Operation {
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        self.take_operands(&mut stack);
+        while let Some(mut op) = stack.pop() {
+            op.take_operands(&mut stack);
+        }
+    }
+}
+
+impl Line<'_> {
+    /// Copies the comment out of the source the line was parsed from, so that it can outlive it.
+    pub fn into_owned(self) -> Line<'static> {
+        let own = |comment: Cow<'_, str>| Cow::Owned(comment.into_owned());
+        match self {
+            Line::Operation {
+                operation,
+                value,
+                running,
+                step,
+                comment,
+                span,
+            } => Line::Operation {
+                operation,
+                value,
+                running,
+                step,
+                comment: own(comment),
+                span,
+            },
+            Line::Subtotal {
+                kind,
+                label,
+                value,
+                written,
+                target,
+                on_target,
+                comment,
+                span,
+            } => Line::Subtotal {
+                kind,
+                label,
+                value,
+                written,
+                target,
+                on_target,
+                comment: own(comment),
+                span,
+            },
+            Line::Assign {
+                name,
+                operation,
+                value,
+                comment,
+                span,
+            } => Line::Assign {
+                name,
+                operation,
+                value,
+                comment: own(comment),
+                span,
+            },
+            Line::Comment(comment, span) => Line::Comment(own(comment), span),
+            Line::Blank(span) => Line::Blank(span),
+        }
+    }
+}
+
+/// How numbers are written, both in documents and in the output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Locale {
+    /// `1,234.5`, with a decimal point.
+    #[default]
+    Point,
+    /// `1.234,5`, with a decimal comma and dots between digit groups, as in most of Europe.
+    Comma,
+}
+
+impl Locale {
+    /// The character between the integer and fractional parts of a number.
+    pub fn decimal_separator(self) -> char {
+        match self {
+            Locale::Point => '.',
+            Locale::Comma => ',',
+        }
+    }
+}
+
+impl FromStr for Locale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "point" => Ok(Locale::Point),
+            "comma" => Ok(Locale::Comma),
+            _ => Err(format!("unknown locale `{s}`")),
+        }
+    }
+}
+
+/// What a [`Line::Subtotal`] computes from the lines before it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SubtotalKind {
+    /// The running total, below a rule of `-`.
+    #[default]
+    Total,
+    /// The mean of the operations since the previous subtotal, below a rule of `~`.
+    Average,
+    /// The smallest interval covering every operation since the previous subtotal, below a rule of
+    /// `|`.
+    Hull,
+}
+
+/// Built-in functions that can be called from expressions, as in `mid([1, 3])`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Function {
+    /// The midpoint of an interval.
+    Mid,
+    /// The same as [`Function::Mid`], for collapsing an interval into a point estimate once
+    /// uncertainty no longer matters. Numbers are left as they are.
+    Point,
+    /// Half the width of an interval.
+    Radius,
+    /// The absolute value.
+    Abs,
+    /// The smaller of two values. For intervals this takes the smaller of each pair of bounds, so
+    /// `min([1, 5], [2, 3])` is `[1, 3]`: the range of `min(x, y)` for `x` and `y` in the intervals.
+    Min,
+    /// The larger of two values, taking the larger of each pair of bounds for intervals.
+    Max,
+    /// The square of a value, the same as `x ^ 2`. This differs from `x * x` on intervals, which
+    /// treats both operands as independent: `[-2, 3] * [-2, 3]` is `[-6, 9]`, while no value in
+    /// `[-2, 3]` has a negative square, so `sq([-2, 3])` is `[0, 9]`.
+    Sq,
+    /// The square root of a non-negative value.
+    Sqrt,
+    /// One divided by the value.
+    Recip,
+    /// Whether the first value contains the second one entirely, as `1` or `0` since there are no
+    /// booleans.
+    Contains,
+    /// Rounding to the nearest whole number, or to as many decimal places as the optional second
+    /// argument says, with halves rounded away from zero. Intervals are rounded outwards.
+    Round,
+    /// Rounding down, to an optional number of decimal places.
+    Floor,
+    /// Rounding up, to an optional number of decimal places.
+    Ceil,
+}
+
+impl Function {
+    /// The name the function is called by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Function::Mid => "mid",
+            Function::Point => "point",
+            Function::Radius => "radius",
+            Function::Abs => "abs",
+            Function::Min => "min",
+            Function::Max => "max",
+            Function::Sq => "sq",
+            Function::Sqrt => "sqrt",
+            Function::Recip => "recip",
+            Function::Contains => "contains",
+            Function::Round => "round",
+            Function::Floor => "floor",
+            Function::Ceil => "ceil",
+        }
+    }
+
+    /// The numbers of arguments the function can take.
+    pub fn arity(self) -> RangeInclusive<usize> {
+        match self {
+            Function::Mid
+            | Function::Point
+            | Function::Radius
+            | Function::Abs
+            | Function::Sq
+            | Function::Sqrt
+            | Function::Recip => 1..=1,
+            Function::Min | Function::Max | Function::Contains => 2..=2,
+            Function::Round | Function::Floor | Function::Ceil => 1..=2,
+        }
+    }
+
+    pub(crate) fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "mid" => Some(Function::Mid),
+            "point" => Some(Function::Point),
+            "radius" => Some(Function::Radius),
+            "abs" => Some(Function::Abs),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "sq" => Some(Function::Sq),
+            "sqrt" => Some(Function::Sqrt),
+            "recip" => Some(Function::Recip),
+            "contains" => Some(Function::Contains),
+            "round" => Some(Function::Round),
+            "floor" => Some(Function::Floor),
+            "ceil" => Some(Function::Ceil),
+            _ => None,
+        }
+    }
+}
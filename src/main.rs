@@ -1,324 +1,754 @@
 use std::{
-    fmt::{self, Write},
     fs::File,
-    io::Read,
+    io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Write},
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+    sync::mpsc,
 };
 
 use ariadne::{Color, Source};
-use chumsky::{
-    pratt::{infix, left},
-    prelude::*,
-    text::{inline_whitespace, newline, whitespace},
+use calc::{
+    check_within, evaluate_traced, import_csv, parse_document_after, parse_document_with,
+    pretty_print, summary, EvalError, EvalOptions, Evaluator, Format, FormatOptions, Line, Locale,
+    Step, SubtotalKind, Value,
 };
+use chumsky::span::SimpleSpan;
+use notify::{Event, RecursiveMode, Watcher};
 use rust_decimal::Decimal;
 
-#[derive(Debug)]
-enum Line {
-    Operation {
-        operation: Operation,
-        comment: String,
-    },
-    Subtotal {
-        value: Option<Value>,
-        comment: String,
-    },
+#[derive(Default)]
+struct Args {
+    paths: Vec<String>,
+    eval: EvalOptions,
+    format: FormatOptions,
+    grand_total: bool,
+    final_total: bool,
+    total_label: Option<String>,
+    check: bool,
+    /// How far written subtotals may be from the computed ones before `--check` reports them.
+    epsilon: Decimal,
+    output: Option<String>,
+    explain: bool,
+    watch: bool,
+    stream: bool,
+    percent: bool,
+    import_csv: Option<String>,
+    parse_only: bool,
+    quiet: bool,
+    summary: bool,
+    /// The first and last source lines to print, counting from 1, with the rest of the document
+    /// still evaluated.
+    from_line: Option<usize>,
+    to_line: Option<usize>,
+    /// How many errors to report for each document before leaving the rest out.
+    max_errors: Option<usize>,
+    /// Whether error reports use color, as resolved from `--color`.
+    color: bool,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum Value {
-    Number(Decimal),
-    Interval(Decimal, Decimal),
+/// When to color error reports.
+#[derive(Default)]
+enum ColorChoice {
+    Never,
+    Always,
+    /// Only when stderr is a terminal.
+    #[default]
+    Auto,
 }
 
-#[derive(Debug)]
-enum Operation {
-    Mul(Box<Operation>, Box<Operation>),
-    Div(Box<Operation>, Box<Operation>),
-    Value(Value),
-}
+impl FromStr for ColorChoice {
+    type Err = ();
 
-impl Value {
-    fn sub(self, value: Value) -> Value {
-        match (self, value) {
-            (Value::Number(n), Value::Number(m)) => Value::Number(n - m),
-            (Value::Number(n), Value::Interval(a, b)) => Value::Interval(n - b, n - a),
-            (Value::Interval(a, b), Value::Number(n)) => Value::Interval(a - n, b - n),
-            (Value::Interval(a, b), Value::Interval(c, d)) => Value::Interval(a - d, b - c),
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "never" => Ok(ColorChoice::Never),
+            "always" => Ok(ColorChoice::Always),
+            "auto" => Ok(ColorChoice::Auto),
+            _ => Err(()),
         }
     }
+}
 
-    fn mul(&self, r: Value) -> Value {
-        match (self, r) {
-            (Value::Number(n), Value::Number(m)) => Value::Number(n * m),
-            (Value::Number(n), Value::Interval(a, b)) => Value::Interval(n * a, n * b),
-            (Value::Interval(a, b), Value::Number(n)) => Value::Interval(a * n, b * n),
-            (Value::Interval(a, b), Value::Interval(c, d)) => {
-                if *a >= 0.into() && c >= 0.into() {
-                    Value::Interval(a * c, b * d)
-                } else {
-                    unimplemented!()
+impl Args {
+    fn parse() -> Result<Args, String> {
+        let mut args = Args::default();
+        let mut color = ColorChoice::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--format" => args.format.format = flag_value(&mut iter, &arg)?,
+                "--precision" => args.format.precision = flag_value(&mut iter, &arg)?,
+                "--round-outward" => args.format.round_outward = true,
+                "--group-digits" => args.format.digit_separator = Some(','),
+                "--locale" => args.format.locale = flag_value(&mut iter, &arg)?,
+                "--indent" => args.format.indent = flag_value(&mut iter, &arg)?,
+                "--align" => args.format.align = flag_value(&mut iter, &arg)?,
+                "--rule-char" => args.format.rule_char = flag_value(&mut iter, &arg)?,
+                "--rel-width" => args.format.rel_width = true,
+                "--reconcile" => args.format.reconcile = true,
+                "--show-op" => args.format.show_op = true,
+                "--no-comments" => args.format.comments = false,
+                "--running" => args.format.running = true,
+                "--comment-width" => args.format.comment_width = Some(flag_value(&mut iter, &arg)?),
+                "--comment-overflow" => {
+                    args.format.comment_overflow = flag_value(&mut iter, &arg)?
                 }
+                "--percent" => args.percent = true,
+                "--sections" => args.eval.sections = true,
+                "--sum" => args.eval.sum = true,
+                "--grand-total" => args.grand_total = true,
+                "--final" => args.final_total = true,
+                "--total-label" => args.total_label = Some(flag_value(&mut iter, &arg)?),
+                "--check" => args.check = true,
+                "--epsilon" => args.epsilon = flag_value(&mut iter, &arg)?,
+                "--explain" => args.explain = true,
+                "--parse-only" => args.parse_only = true,
+                "--quiet" | "-q" => args.quiet = true,
+                "--summary" => args.summary = true,
+                "--color" => color = flag_value(&mut iter, &arg)?,
+                "--from-line" => args.from_line = Some(flag_value(&mut iter, &arg)?),
+                "--to-line" => args.to_line = Some(flag_value(&mut iter, &arg)?),
+                "--max-errors" => args.max_errors = Some(flag_value(&mut iter, &arg)?),
+                "--import-csv" => args.import_csv = Some(flag_value(&mut iter, &arg)?),
+                "--watch" => args.watch = true,
+                "--stream" => args.stream = true,
+                "--output" | "-o" => args.output = Some(flag_value(&mut iter, &arg)?),
+                _ if arg.starts_with("--") => return Err(format!("unknown flag `{arg}`")),
+                _ => args.paths.push(arg),
             }
         }
-    }
-
-    fn div(&self, r: Value) -> Value {
-        match (self, r) {
-            (Value::Number(n), Value::Number(m)) => Value::Number(n / m),
-            (Value::Number(n), Value::Interval(a, b)) => Value::Interval(n / a, n / b),
-            (Value::Interval(a, b), Value::Number(n)) => Value::Interval(a / n, b / n),
-            (Value::Interval(_, _), Value::Interval(_, _)) => {
-                unimplemented!()
+        // Other characters wouldn't read back as the rule of a total.
+        if !matches!(args.format.rule_char, '-' | '=') {
+            return Err("`--rule-char` expects `-` or `=`".to_string());
+        }
+        // Decimals can't have more places than that.
+        if args.format.precision > 28 {
+            return Err("`--precision` expects at most 28 decimal places".to_string());
+        }
+        // Digits are grouped with dots where commas are decimal separators.
+        if args.format.locale == Locale::Comma && args.format.digit_separator.is_some() {
+            args.format.digit_separator = Some('.');
+        }
+        if args.epsilon < Decimal::ZERO {
+            return Err(format!("invalid value `{}` for `--epsilon`", args.epsilon));
+        }
+        if let (Some(from), Some(to)) = (args.from_line, args.to_line) {
+            if from > to {
+                return Err(format!("`--from-line {from}` comes after `--to-line {to}`"));
             }
         }
+        args.color = match color {
+            ColorChoice::Never => false,
+            ColorChoice::Always => true,
+            ColorChoice::Auto => std::io::stderr().is_terminal(),
+        };
+        if args.watch && (args.paths.len() != 1 || args.paths[0] == "-") {
+            return Err("`--watch` expects exactly one file".to_string());
+        }
+        if args.stream {
+            if args.paths.len() > 1 {
+                return Err("`--stream` expects at most one file".to_string());
+            }
+            if args.format.format != Format::Text {
+                return Err("`--stream` only supports the text format".to_string());
+            }
+            let unsupported = [
+                ("--watch", args.watch),
+                ("--percent", args.percent),
+                ("--import-csv", args.import_csv.is_some()),
+                ("--parse-only", args.parse_only),
+                ("--quiet", args.quiet),
+                ("--summary", args.summary),
+                ("--final", args.final_total),
+                ("--from-line", args.from_line.is_some()),
+                ("--to-line", args.to_line.is_some()),
+            ];
+            if let Some((flag, _)) = unsupported.iter().find(|(_, set)| *set) {
+                return Err(format!("`--stream` can't be combined with `{flag}`"));
+            }
+        }
+        Ok(args)
     }
 }
 
-fn parse_value<'a>() -> impl Parser<'a, &'a str, Value, extra::Err<Rich<'a, char>>> {
-    let number = just('-')
-        .or_not()
-        .then(text::int(10))
-        .then(just('.').then(text::digits(10)).or_not())
-        .to_slice()
-        .map(|s: &str| s.parse().unwrap())
-        .boxed();
-
-    let interval = number
-        .clone()
-        .then_ignore(just(',').padded())
-        .then(number.clone())
-        .padded_by(inline_whitespace())
-        .delimited_by(just('['), just(']'));
-
-    choice((
-        number.map(Value::Number).labelled("number"),
-        interval.map(|(a, b)| Value::Interval(a, b)).labelled("interval"),
-    ))
+fn flag_value<T: FromStr>(iter: &mut impl Iterator<Item = String>, flag: &str) -> Result<T, String> {
+    let value = iter
+        .next()
+        .ok_or_else(|| format!("`{flag}` expects a value"))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value `{value}` for `{flag}`"))
 }
 
-// This can swallow useful error messages so some fix would be needed int the future
-fn parse_operation<'a>() -> impl Parser<'a, &'a str, Operation, extra::Err<Rich<'a, char>>> {
-    let value = inline_whitespace()
-        .ignore_then(parse_value())
-        .map(Operation::Value);
-
-    value.pratt((
-        infix(
-            left(1),
-            inline_whitespace().ignore_then(just('*')),
-            |l, r| Operation::Mul(Box::new(l), Box::new(r)),
-        ),
-        infix(
-            left(1),
-            inline_whitespace().ignore_then(just('/')),
-            |l, r| Operation::Div(Box::new(l), Box::new(r)),
-        ),
-    ))
-}
+fn main() -> std::io::Result<()> {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+    };
 
-fn parse_subtotal<'a>() -> impl Parser<'a, &'a str, Line, extra::Err<Rich<'a, char>>> {
-    let subtotal_line = one_of("-")
-        .ignored()
-        .repeated()
-        .ignore_then(
-            inline_whitespace().then(newline()).labelled("result line")
-        );
-
-    let comment = none_of("\n")
-        .ignored()
-        .repeated()
-        .to_slice()
-        .map(ToString::to_string);
-
-    let value_comment = inline_whitespace().at_least(1).ignore_then(comment.clone());
-
-    let no_value = comment.padded_by(inline_whitespace()).map(|c| (None, c));
-
-    let value = parse_value()
-        .map(Some)
-        .then(value_comment.or_not().map(|a| a.unwrap_or_default()));
-
-    let result_line = choice((value, no_value));
-    subtotal_line
-        .ignore_then(result_line)
-        .map(|(v, c)| Line::Subtotal {
-            value: v,
-            comment: c,
-        })
-}
+    if args.watch {
+        watch(&args.paths[0], &args);
+    }
 
-fn parse_operation_line<'a>() -> impl Parser<'a, &'a str, Line, extra::Err<Rich<'a, char>>> {
-    let value = parse_operation();
+    if args.stream {
+        let path = args.paths.first().map_or("-", String::as_str);
+        let failed = stream(path, &args).unwrap_or_else(|e| {
+            eprintln!("error: cannot stream `{path}`: {e}");
+            true
+        });
+        if failed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    let comment = inline_whitespace()
-        .at_least(1)
-        .labelled("space")
-        .ignore_then(none_of("\n").ignored().repeated().to_slice().labelled("comment"))
-        .map(ToString::to_string)
-        .or_not();
+    let mut failed = false;
+    let mut out = String::new();
+    let paths = if args.paths.is_empty() {
+        vec!["-"]
+    } else {
+        args.paths.iter().map(String::as_str).collect()
+    };
+    for path in &paths {
+        let (name, buf) = match read_input(path) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("error: cannot read `{path}`: {e}");
+                failed = true;
+                continue;
+            }
+        };
+        if paths.len() > 1 {
+            out.push_str(&format!("==> {name} <==\n"));
+        }
+        failed |= run(name, &buf, &args, &mut out);
+    }
 
-    value.then(comment).map(|(v, comment)| Line::Operation {
-        operation: v,
-        comment: comment.unwrap_or(String::new()),
-    })
-}
+    match &args.output {
+        Some(path) => std::fs::write(path, out)?,
+        None => print!("{out}"),
+    }
 
-fn parse_line<'a>() -> impl Parser<'a, &'a str, Line, extra::Err<Rich<'a, char>>> {
-    choice((parse_operation_line(), parse_subtotal()))
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
 }
 
-fn pretty_print_value(fmt: &mut impl Write, v: Value) -> fmt::Result {
-    match v {
-        Value::Number(n) => write!(fmt, "{}", n.round_dp(2).normalize()),
-        Value::Interval(a, b) => write!(
-            fmt,
-            "[{}, {}]",
-            a.round_dp(2).normalize(),
-            b.round_dp(2).normalize()
-        ),
+/// The name stdin is reported under, which ariadne only ever displays.
+const STDIN: &str = "<stdin>";
+
+/// Reads the document at `path`, or stdin for `-`, along with the name to report it under.
+fn read_input(path: &str) -> std::io::Result<(&str, String)> {
+    if path == "-" {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf)?;
+        Ok((STDIN, buf))
+    } else {
+        Ok((path, std::fs::read_to_string(path)?))
     }
 }
 
-fn pretty_print_operation(fmt: &mut impl Write, op: &Operation) -> fmt::Result {
-    match op {
-        Operation::Mul(l, r) => {
-            pretty_print_operation(fmt, l)?;
-            write!(fmt, " * ")?;
-            pretty_print_operation(fmt, r)
+/// Evaluates the document at `path`, or stdin for `-`, a section at a time, writing out each
+/// section as soon as its subtotal is reached so that only one is ever held in memory.
+///
+/// Values are aligned within each section rather than across the whole document. Returns whether
+/// the document should fail the run.
+fn stream(path: &str, args: &Args) -> std::io::Result<bool> {
+    let (name, input): (&str, Box<dyn BufRead>) = if path == "-" {
+        (STDIN, Box::new(std::io::stdin().lock()))
+    } else {
+        (path, Box::new(BufReader::new(File::open(path)?)))
+    };
+    let mut output: Box<dyn Write> = match &args.output {
+        Some(output) => Box::new(BufWriter::new(File::create(output)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut evaluator = Evaluator::new(args.eval.clone());
+    let mut failed = false;
+    let mut section = String::new();
+    // The number of lines before the current section, to report errors at their line in the file.
+    let mut section_start = 0;
+    let mut section_lines = 0;
+    let mut after_rule = false;
+    let mut lines = input.lines();
+    loop {
+        let line = lines.next().transpose()?;
+        if let Some(line) = &line {
+            section.push_str(line);
+            section.push('\n');
+            section_lines += 1;
+
+            // A section ends with its subtotal: with the result line following a rule, or with the
+            // line itself when the subtotal takes only one.
+            let ends_section = if after_rule {
+                after_rule = false;
+                true
+            } else {
+                let subtotal = subtotal_line(line, args.format.locale);
+                after_rule = subtotal == Some(false);
+                subtotal == Some(true)
+            };
+            if !ends_section {
+                continue;
+            }
         }
-        Operation::Div(l, r) => {
-            pretty_print_operation(fmt, l)?;
-            write!(fmt, " / ")?;
-            pretty_print_operation(fmt, r)
+
+        if !section.is_empty() {
+            match stream_section(name, &section, section_start, &mut evaluator, args) {
+                Some((out, mismatched)) => {
+                    output.write_all(out.as_bytes())?;
+                    failed |= mismatched;
+                }
+                None => return Ok(true),
+            }
+            section.clear();
+            section_start += section_lines;
+            section_lines = 0;
+        }
+        if line.is_none() {
+            break;
         }
-        Operation::Value(v) => pretty_print_value(fmt, *v),
     }
-}
 
-fn pretty_print(lines: Vec<Line>) -> Result<String, std::fmt::Error> {
-    let lhs: Vec<_> = lines
-        .iter()
-        .map(|line| match line {
-            Line::Operation { operation, .. } => {
-                let mut out = String::new();
-                pretty_print_operation(&mut out, operation).unwrap();
-                Some(out)
+    let total = match evaluator.finish() {
+        Ok(total) => total,
+        Err((e, _)) => {
+            eprintln!("error: {e}");
+            return Ok(true);
+        }
+    };
+    if args.grand_total {
+        let line = Line::Subtotal {
+            kind: SubtotalKind::Total,
+            label: None,
+            value: total,
+            written: None,
+            target: None,
+            on_target: None,
+            comment: args.total_label.as_deref().unwrap_or("TOTAL").into(),
+            span: SimpleSpan::from(0..0),
+        };
+        match pretty_print(vec![line], &args.format) {
+            Ok(out) => output.write_all(out.as_bytes())?,
+            Err(_) => {
+                eprintln!("error: cannot format `{name}`");
+                return Ok(true);
             }
-            Line::Subtotal { value, .. } => value.map(|value| {
-                let mut out = String::new();
-                pretty_print_value(&mut out, value).unwrap();
-                out
-            }),
-        })
-        .collect();
+        }
+    }
+    writeln!(output)?;
+    output.flush()?;
+    Ok(failed)
+}
 
-    let lhs_col = lhs
-        .iter()
-        .map(|l| l.as_ref().map(|l| l.len()).unwrap_or(0))
-        .max()
-        .unwrap_or(0);
-
-    let mut s = String::new();
-    for (lhs, line) in lhs.into_iter().zip(lines) {
-        match line {
-            Line::Operation { comment, .. } => {
-                writeln!(
-                    &mut s,
-                    "{:>width$} {}",
-                    lhs.unwrap(),
-                    comment,
-                    width = lhs_col
-                )?;
-            }
-            Line::Subtotal { comment, .. } => {
-                writeln!(&mut s, "{:-<width$}", "", width = lhs_col)?;
+// Whether `line` is a subtotal complete on its own, like a `total:` line, or the rule of one whose
+// result line comes next, or `None` if it's neither.
+fn subtotal_line(line: &str, locale: Locale) -> Option<bool> {
+    if !line.starts_with(['-', '=', '~', '|']) && !line.trim_start().starts_with("total:") {
+        return None;
+    }
+    // Parsing the line tells rules apart from lines starting with the same characters, like `- 5`
+    // or the continued `| [3, 4]`. It's given an empty result line to go with, so that only the
+    // subtotals complete on their own line end up with a comment, save `total:` lines.
+    match parse_document_with(&format!("{line}\n"), locale).as_deref() {
+        Ok([Line::Subtotal { comment, .. }]) => {
+            Some(!comment.is_empty() || line.trim_start().starts_with("total:"))
+        }
+        _ => None,
+    }
+}
 
-                let lhs = if let Some(v) = lhs { v } else { String::new() };
-                writeln!(&mut s, "{:>width$} {comment}", lhs, width = lhs_col)?;
-                writeln!(&mut s)?;
+// Evaluates and renders one section of a streamed document, starting after the first `first_line`
+// lines of the file, returning its rendering and whether any of its subtotals disagree with the
+// computed ones, or `None` once errors have been reported.
+fn stream_section(
+    name: &str,
+    src: &str,
+    first_line: usize,
+    evaluator: &mut Evaluator,
+    args: &Args,
+) -> Option<(String, bool)> {
+    // Variables defined in earlier sections carry over, like their values in the evaluator.
+    let mut file = match parse_document_after(src, args.format.locale, evaluator.variables()) {
+        Ok(file) => file,
+        Err(errs) => {
+            let errs = errs.into_iter().map(|e| (*e.span(), e.to_string(), e.reason().to_string()));
+            report_all(name, src, first_line, args, errs);
+            return None;
+        }
+    };
+
+    let mut trace = Vec::new();
+    for line in &mut file {
+        match evaluator.line(line) {
+            Ok(step) => trace.extend(step),
+            Err((e, span)) => {
+                report_eval(name, src, first_line, args.color, span, &e);
+                return None;
             }
         }
     }
-    Ok(s)
-}
+    if args.explain {
+        explain(&trace);
+    }
 
-fn evaluate_operation(op: &Operation) -> Value {
-    match op {
-        Operation::Mul(l, r) => {
-            let l = evaluate_operation(l);
-            let r = evaluate_operation(r);
+    let mismatches = if args.check {
+        check_within(&file, args.epsilon)
+    } else {
+        Vec::new()
+    };
+    let errs = mismatches.iter().map(|(e, span)| (*span, e.to_string(), "written here".into()));
+    report_all(name, src, first_line, args, errs);
+
+    match pretty_print(file, &args.format) {
+        Ok(out) => Some((out, !mismatches.is_empty())),
+        Err(_) => {
+            eprintln!("error: cannot format `{name}`");
+            None
+        }
+    }
+}
 
-            l.mul(r)
+/// Re-runs the document at `path` every time it is modified, until interrupted.
+fn watch(path: &str, args: &Args) -> ! {
+    // Editors often save by replacing the file rather than writing to it, which would end a watch
+    // on the file itself, so its directory is watched instead.
+    let file = Path::new(path);
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
+    let watcher = notify::recommended_watcher(tx)
+        .and_then(|mut watcher| watcher.watch(dir, RecursiveMode::NonRecursive).map(|_| watcher));
+    // Kept until the loop ends, which it never does, for the events to keep coming.
+    let _watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("error: cannot watch `{path}`: {e}");
+            std::process::exit(1);
         }
-        Operation::Div(l, r) => {
-            let l = evaluate_operation(l);
-            let r = evaluate_operation(r);
+    };
 
-            l.div(r)
+    // A single save can come as several events, so the document is only run again once its
+    // contents have changed.
+    let mut last = None;
+    loop {
+        match std::fs::read_to_string(path) {
+            // Editors may briefly remove the file while saving it.
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            read => {
+                let read = read.map_err(|e| e.to_string());
+                if last.as_ref() != Some(&read) {
+                    // Clear the screen and move the cursor back to the top, before any errors get
+                    // printed.
+                    print!("\x1b[2J\x1b[H");
+                    let _ = std::io::stdout().flush();
+                    match &read {
+                        Ok(buf) => {
+                            let mut out = String::new();
+                            run(path, buf, args, &mut out);
+                            match &args.output {
+                                Some(output) => {
+                                    if let Err(e) = std::fs::write(output, out) {
+                                        eprintln!("error: cannot write `{output}`: {e}");
+                                    }
+                                }
+                                None => print!("{out}"),
+                            }
+                        }
+                        Err(e) => eprintln!("error: cannot read `{path}`: {e}"),
+                    }
+                    last = Some(read);
+                }
+            }
+        }
+        // Waits for the next event about the file, skipping those about the rest of its directory.
+        loop {
+            match rx.recv() {
+                Ok(Ok(event)) if event.paths.iter().any(|p| p.file_name() == file.file_name()) => {
+                    break
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => eprintln!("error: cannot watch `{path}`: {e}"),
+                Err(_) => unreachable!("the watcher outlives the loop"),
+            }
         }
-        Operation::Value(v) => *v,
     }
 }
 
-fn evaluate(lines: &mut [Line]) {
-    if lines.is_empty() {
-        return;
+/// Evaluates the document `src` and appends its rendering to `out`, reporting any errors on the way.
+///
+/// Returns whether the document should fail the run.
+fn run(path: &str, src: &str, args: &Args, out: &mut String) -> bool {
+    let parsed = match &args.import_csv {
+        Some(column) => import_csv(src, column).map_err(|errs| {
+            let errs = errs.into_iter().map(|(e, span)| (span, e.to_string(), "here".into()));
+            report_all(path, src, 0, args, errs)
+        }),
+        None => parse_document_with(src, args.format.locale).map_err(|errs| {
+            let errs = errs.into_iter().map(|e| (*e.span(), e.to_string(), e.reason().to_string()));
+            report_all(path, src, 0, args, errs)
+        }),
+    };
+    let Ok(mut file) = parsed else {
+        return true;
+    };
+
+    if args.parse_only {
+        out.push_str(&format!("{file:#?}\n"));
+        return false;
     }
-    if matches!(lines[0], Line::Subtotal { .. }) {
-        return;
+
+    let total = match evaluate_traced(&mut file, &args.eval) {
+        Ok((total, trace)) => {
+            if args.explain {
+                explain(&trace);
+            }
+            total
+        }
+        Err((e, span)) => {
+            report_eval(path, src, 0, args.color, span, &e);
+            return true;
+        }
     };
 
-    let mut accu = match &lines[0] {
-        Line::Operation { operation, .. } => evaluate_operation(&operation),
-        Line::Subtotal { .. } => return,
+    if args.format.format == Format::Ledger {
+        let interval = file.iter().find_map(|line| match line {
+            Line::Operation {
+                value: Some(Value::Interval(..)),
+                span,
+                ..
+            }
+            | Line::Subtotal {
+                value: Some(Value::Interval(..)),
+                span,
+                ..
+            } => Some(*span),
+            _ => None,
+        });
+        if let Some(span) = interval {
+            let message = "the ledger format doesn't support intervals";
+            report(path, src, 0, args.color, span, message, "this is an interval");
+            return true;
+        }
+    }
+
+    let mismatches = if args.check {
+        check_within(&file, args.epsilon)
+    } else {
+        Vec::new()
     };
+    let errs = mismatches.iter().map(|(e, span)| (*span, e.to_string(), "written here".into()));
+    report_all(path, src, 0, args, errs);
+
+    if args.from_line.is_some() || args.to_line.is_some() {
+        let lines = args.from_line.unwrap_or(1)..=args.to_line.unwrap_or(usize::MAX);
+        slice(&mut file, src, lines);
+    }
 
-    for l in &mut lines[1..] {
-        match l {
-            Line::Operation { operation, .. } => accu = accu.sub(evaluate_operation(operation)),
-            Line::Subtotal { value, .. } => *value = Some(accu),
+    if args.final_total {
+        // Only needed when operations come after the last subtotal, if there is one at all.
+        let last = file.iter().rev().find_map(|line| match line {
+            Line::Operation { running, .. } => Some(*running),
+            Line::Subtotal { .. } => Some(None),
+            _ => None,
+        });
+        if let Some(Some(running)) = last {
+            file.push(Line::Subtotal {
+                kind: SubtotalKind::Total,
+                label: None,
+                value: Some(running),
+                written: None,
+                target: None,
+                on_target: None,
+                comment: "".into(),
+                span: SimpleSpan::from(src.len()..src.len()),
+            });
         }
     }
-}
 
-fn main() -> std::io::Result<()> {
-    let Some(arg) = std::env::args().nth(1) else {
-        return Ok(());
+    if args.grand_total {
+        file.push(Line::Subtotal {
+            kind: SubtotalKind::Total,
+            label: None,
+            value: total,
+            written: None,
+            target: None,
+            on_target: None,
+            comment: args.total_label.as_deref().unwrap_or("TOTAL").into(),
+            span: SimpleSpan::from(src.len()..src.len()),
+        });
+    }
+
+    if args.quiet {
+        if let Some(result) = result(&file) {
+            let precision = args.format.precision as usize;
+            out.push_str(&format!("{result:.precision$}\n"));
+        }
+        return !mismatches.is_empty();
+    }
+
+    let footer = if args.summary {
+        match summary(&file) {
+            Ok((count, sum)) => {
+                let precision = args.format.precision as usize;
+                let sum = sum.map_or_else(String::new, |sum| format!("{sum:.precision$}"));
+                format!("count: {count}\nsum: {sum}\n")
+            }
+            Err((e, span)) => {
+                report(path, src, 0, args.color, span, e.to_string(), "in this sum");
+                return true;
+            }
+        }
+    } else {
+        String::new()
     };
 
-    let mut file = File::open(arg.clone())?;
-    let mut buf = String::new();
-    File::read_to_string(&mut file, &mut buf)?;
+    let mut format = args.format.clone();
+    if args.percent {
+        format.percent_of = total;
+    }
+    let Ok(f) = pretty_print(file, &format) else {
+        eprintln!("error: cannot format `{path}`");
+        return true;
+    };
+    out.push_str(&f);
+    out.push('\n');
+    out.push_str(&footer);
 
-    let parse_result = parse_line()
-        .then_ignore(whitespace())
-        .repeated()
-        .collect::<Vec<_>>()
-        .then_ignore(end())
-        .parse(&buf)
-        .into_result();
+    !mismatches.is_empty()
+}
+
+// Keeps only the lines of an evaluated document that start within `lines`, counting from 1, so that
+// their values still account for the lines before them.
+fn slice(file: &mut Vec<Line>, src: &str, lines: RangeInclusive<usize>) {
+    let starts: Vec<usize> = std::iter::once(0)
+        .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+        .collect();
+    file.retain(|line| {
+        let span = match line {
+            Line::Operation { span, .. }
+            | Line::Subtotal { span, .. }
+            | Line::Assign { span, .. }
+            | Line::Comment(_, span)
+            | Line::Blank(span) => span,
+        };
+        lines.contains(&starts.partition_point(|&start| start <= span.start))
+    });
+}
+
+// The value of the last subtotal, or failing that the final running total.
+fn result(file: &[Line]) -> Option<Value> {
+    let subtotal = file.iter().rev().find_map(|line| match line {
+        Line::Subtotal { value, .. } => *value,
+        _ => None,
+    });
+    subtotal.or_else(|| {
+        file.iter().rev().find_map(|line| match line {
+            Line::Operation { running, .. } => *running,
+            _ => None,
+        })
+    })
+}
 
-    match parse_result {
-        Ok(mut file) => {
-            evaluate(&mut file);
-            let f = pretty_print(file).unwrap();
-            println!("{f}")
+// Prints the steps of the running total to stderr, one section per line.
+fn explain(trace: &[Step]) {
+    let mut line = String::new();
+    for step in trace {
+        if let Step::Start(_) = step {
+            if !line.is_empty() {
+                eprintln!("{line}");
+            }
+            line = step.to_string();
+        } else {
+            line.push_str(&format!(" → {step}"));
         }
-        Err(errs) => {
-            errs.into_iter().for_each(|e| {
-                ariadne::Report::build(ariadne::ReportKind::Error, &arg[..], e.span().start)
-                    .with_message(e.to_string())
-                    .with_label(
-                        ariadne::Label::new((&arg[..], e.span().into_range()))
-                            .with_message(e.reason().to_string())
-                            .with_color(Color::Red),
-                    )
-                    .finish()
-                    .eprint((&arg[..], Source::from(&buf)))
-                    .unwrap()
-            });
+    }
+    if !line.is_empty() {
+        eprintln!("{line}");
+    }
+}
+
+// Reports each of `errors`, given as a span, message and label, up to `--max-errors` of them.
+fn report_all(
+    path: &str,
+    src: &str,
+    first_line: usize,
+    args: &Args,
+    errors: impl IntoIterator<Item = (SimpleSpan, String, String)>,
+) {
+    let mut left_out = 0;
+    for (i, (span, message, label)) in errors.into_iter().enumerate() {
+        if args.max_errors.is_some_and(|max| i >= max) {
+            left_out += 1;
+            continue;
         }
+        report(path, src, first_line, args.color, span, message, label);
+    }
+    if left_out > 0 {
+        let errors = if left_out == 1 { "error" } else { "errors" };
+        eprintln!("(… and {left_out} more {errors})");
     }
+}
 
-    Ok(())
+// Reports an error at `span` in `src`, which starts after the first `first_line` lines of the file.
+fn report(
+    path: &str,
+    src: &str,
+    first_line: usize,
+    color: bool,
+    span: SimpleSpan,
+    message: impl ToString,
+    label: impl ToString,
+) {
+    report_labelled(path, src, first_line, color, span, message, [(span, label.to_string())]);
+}
+
+// Reports an error in evaluating the expression at `span`, pointing at the operands to blame for it
+// as well when there are any.
+fn report_eval(
+    path: &str,
+    src: &str,
+    first_line: usize,
+    color: bool,
+    span: SimpleSpan,
+    e: &EvalError,
+) {
+    let mut labels = vec![(span, "in this expression".to_string())];
+    if let EvalError::Undefined { left, right, .. } = e {
+        labels.extend(left.map(|left| (left, "this operand".to_string())));
+        labels.extend(right.map(|right| (right, "and this one".to_string())));
+    }
+    report_labelled(path, src, first_line, color, span, e.to_string(), labels);
+}
+
+// Like `report`, with several labels, the first one in red and the others in yellow.
+fn report_labelled(
+    path: &str,
+    src: &str,
+    first_line: usize,
+    color: bool,
+    span: SimpleSpan,
+    message: impl ToString,
+    labels: impl IntoIterator<Item = (SimpleSpan, String)>,
+) {
+    let labels = labels.into_iter().enumerate().map(|(i, (span, label))| {
+        ariadne::Label::new((path, span.into_range()))
+            .with_message(label)
+            .with_color(if i == 0 { Color::Red } else { Color::Yellow })
+    });
+    ariadne::Report::build(ariadne::ReportKind::Error, path, span.start)
+        .with_config(ariadne::Config::default().with_color(color))
+        .with_message(message)
+        .with_labels(labels)
+        .finish()
+        .eprint((path, Source::from(src).with_display_line_offset(first_line)))
+        .unwrap()
 }
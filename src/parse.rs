@@ -0,0 +1,515 @@
+use std::{borrow::Cow, collections::HashMap, rc::Rc};
+
+use chumsky::{
+    pratt::{infix, left, prefix, right},
+    prelude::*,
+    span::SimpleSpan,
+    text::{inline_whitespace, newline},
+};
+
+use rust_decimal::Decimal;
+
+use crate::{Bound, Function, Line, Locale, Openness, Operation, Spanned, SubtotalKind, Value};
+
+/// Parses a whole document into its lines.
+pub fn parse_document(src: &str) -> Result<Vec<Line<'_>>, Vec<Rich<'_, char>>> {
+    parse_document_with(src, Locale::default())
+}
+
+/// Like [`parse_document`], but for a document with numbers written the way `locale` writes them.
+pub fn parse_document_with(
+    src: &str,
+    locale: Locale,
+) -> Result<Vec<Line<'_>>, Vec<Rich<'_, char>>> {
+    parse_document_after(src, locale, [])
+}
+
+/// Like [`parse_document_with`], but for a document continuing one whose lines defined the
+/// variables `defined`, as when streaming it a section at a time.
+pub fn parse_document_after<'a>(
+    src: &'a str,
+    locale: Locale,
+    defined: impl IntoIterator<Item = &str>,
+) -> Result<Vec<Line<'a>>, Vec<Rich<'a, char>>> {
+    let lines = parse_line(locale, definitions(src, defined))
+        .separated_by(newline())
+        .allow_trailing()
+        .collect::<Vec<_>>();
+    // Some editors start files with a byte order mark, which isn't part of the first line. Skipping
+    // it here rather than stripping it beforehand keeps spans pointing into `src`.
+    just('\u{FEFF}')
+        .or_not()
+        .ignore_then(lines)
+        .then_ignore(end())
+        // Blank lines at the very end are only there to end the file, not to space anything out.
+        .map(|mut lines| {
+            while let Some(Line::Blank(_)) = lines.last() {
+                lines.pop();
+            }
+            lines
+        })
+        .parse(src)
+        .into_result()
+}
+
+// Where each variable is defined, as the offset of the end of the first line defining it. Only the
+// lines after that can use it.
+type Defined = Rc<HashMap<String, usize>>;
+
+// Finds the variables of `src`, defined by an assignment or a subtotal label, on top of `defined`.
+fn definitions(src: &str, defined: impl IntoIterator<Item = &str>) -> Defined {
+    let mut found: HashMap<_, _> = defined.into_iter().map(|name| (name.to_string(), 0)).collect();
+    let definition = parse_definition();
+    let mut end = 0;
+    for line in src.split_inclusive('\n') {
+        end += line.len();
+        // Like the parser proper, skipping the byte order mark some files start with.
+        let line = line.trim_start_matches('\u{FEFF}');
+        if let Some(name) = definition.parse(line).into_output() {
+            found.entry(name.to_string()).or_insert(end);
+        }
+    }
+    Rc::new(found)
+}
+
+// The name defined by a line, ignoring the rest of it, which gets parsed for real later on.
+fn parse_definition<'a>() -> impl Parser<'a, &'a str, &'a str, extra::Err<Rich<'a, char>>> {
+    let assign = text::ident().then_ignore(just('=').padded_by(inline_whitespace()));
+    let label = one_of("-=~|")
+        .repeated()
+        .at_least(3)
+        .ignore_then(inline_whitespace().at_least(1))
+        .ignore_then(text::ident())
+        .then_ignore(just(':'));
+    inline_whitespace()
+        .ignore_then(choice((assign, label)))
+        .then_ignore(any().repeated())
+}
+
+fn parse_value<'a>(locale: Locale) -> impl Parser<'a, &'a str, Value, extra::Err<Rich<'a, char>>> {
+    let point = locale.decimal_separator();
+    // Digit groups may be separated by single underscores, as in `1_000_000`, or by dots where they
+    // don't stand for the decimal point, as in `1.000.000`.
+    let grouping = match locale {
+        Locale::Point => "_",
+        Locale::Comma => "_.",
+    };
+    let digits = text::digits(10).separated_by(one_of(grouping)).at_least(1);
+
+    // A leading `+` is accepted, as in `+12.50`, but means nothing.
+    let number = one_of("+-")
+        .or_not()
+        .then(digits.clone())
+        .then(just(point).then(digits).or_not())
+        .then(
+            one_of("eE")
+                .then(one_of("+-").or_not())
+                .then(text::digits(10))
+                .or_not(),
+        )
+        .to_slice()
+        .try_map(move |s: &str, span| {
+            let s: String = s
+                .strip_prefix('+')
+                .unwrap_or(s)
+                .chars()
+                .filter(|c| !grouping.contains(*c))
+                .map(|c| if c == point { '.' } else { c })
+                .collect();
+            let n = if s.contains(['e', 'E']) {
+                Decimal::from_scientific(&s)
+            } else {
+                s.parse::<Decimal>()
+            };
+            // Literals outside of `Decimal`'s range end up here.
+            n.map_err(|e| Rich::custom(span, format!("invalid number `{s}`: {e}")))
+        })
+        // Percentages are plain numbers as far as arithmetic is concerned: `20%` is just `0.2`.
+        .then(just('%').or_not())
+        .map(|(n, percent)| match percent {
+            Some(_) => n / Decimal::ONE_HUNDRED,
+            None => n,
+        })
+        .boxed();
+
+    let bound = choice((
+        number.clone().map(Bound::Finite),
+        just("-inf").to(Bound::NegInf),
+        just("inf").to(Bound::PosInf),
+    ));
+
+    // Parentheses leave their bound out of the interval, as in `[0, 1)`.
+    let interval = one_of("[(")
+        .then(
+            bound
+                .clone()
+                // Not `padded`, which would also let an interval run across lines.
+                .then_ignore(just(',').padded_by(inline_whitespace()))
+                .then(bound)
+                .padded_by(inline_whitespace()),
+        )
+        .then(one_of("])"))
+        .map(|((open, (a, b)), close)| {
+            let (lower, upper) = (open == '(', close == ')');
+            // Bounds given in reverse order keep their brackets, so `(5, 1]` is `[1, 5)`.
+            let (a, b, lower, upper) = if a > b {
+                (b, a, upper, lower)
+            } else {
+                (a, b, lower, upper)
+            };
+            Value::Interval(a, b, Openness { lower, upper })
+        });
+
+    // `5 ± 0.2` is shorthand for `[4.8, 5.2]`.
+    let uncertain = number
+        .clone()
+        .then_ignore(
+            choice((just("±"), just("+-"))).padded_by(inline_whitespace()),
+        )
+        .then(number.clone());
+
+    choice((
+        uncertain
+            .map_with(|(center, radius), e| (center, radius, e.span()))
+            // Reported without failing the branch, which would have `5 ± 0.2` read as `5` followed
+            // by a comment.
+            .validate(|(center, radius, span): (Decimal, Decimal, SimpleSpan), _, emitter| {
+                match (center.checked_sub(radius), center.checked_add(radius)) {
+                    (Some(a), Some(b)) => Value::interval(a, b),
+                    _ => {
+                        let message = format!("`{center} ± {radius}` is too large to represent");
+                        emitter.emit(Rich::custom(span, message));
+                        Value::Number(center)
+                    }
+                }
+            })
+            .labelled("uncertain number"),
+        number.map(Value::Number).labelled("number"),
+        interval.labelled("interval"),
+    ))
+}
+
+// Builds a binary operation spanning both its operands.
+fn binary(
+    node: fn(Box<Operation>, Box<Operation>, SimpleSpan) -> Operation,
+) -> impl Fn(Spanned<Operation>, Spanned<Operation>) -> Spanned<Operation> + Clone {
+    move |(l, l_span), (r, r_span)| {
+        let span = SimpleSpan::from(l_span.start..r_span.end);
+        (node(Box::new(l), Box::new(r), span), span)
+    }
+}
+
+// When an operator isn't followed by an operand, pratt backtracks to just before the operator and
+// succeeds, so errors about missing operands are never produced here: see `parse_operation_line`.
+fn parse_operation<'a>(
+    locale: Locale,
+    defined: Defined,
+) -> impl Parser<'a, &'a str, Operation, extra::Err<Rich<'a, char>>> {
+    // Operations are parsed along with their spans, to give the nodes built out of them theirs.
+    recursive(move |operation| {
+        let value = parse_value(locale).map_with(|v, e| Operation::Value(v, e.span()));
+
+        let group = operation
+            .clone()
+            .map(|(op, _)| op)
+            .then_ignore(inline_whitespace())
+            .delimited_by(just('('), just(')'));
+
+        let args = operation
+            .clone()
+            .map(|(op, _)| op)
+            .then_ignore(inline_whitespace())
+            .separated_by(just(','))
+            .at_least(1)
+            .collect::<Vec<_>>()
+            .delimited_by(just('('), just(')'));
+
+        let call = text::ident()
+            .try_map(|name: &str, span| {
+                Function::from_name(name)
+                    .ok_or_else(|| Rich::custom(span, format!("unknown function `{name}`")))
+            })
+            .then(args)
+            .try_map(|(name, args), span| {
+                let arity = name.arity();
+                if arity.contains(&args.len()) {
+                    return Ok(Operation::Call { name, args });
+                }
+                let expected = if arity.start() == arity.end() {
+                    format!("{}", arity.start())
+                } else {
+                    format!("{} to {}", arity.start(), arity.end())
+                };
+                Err(Rich::custom(
+                    span,
+                    format!("`{}` expects {expected} argument(s)", name.name()),
+                ))
+            });
+
+        // Only names defined on an earlier line are variables. Others end the operation, to be
+        // read as the start of its comment, as in `100 - groceries`.
+        let var = text::ident().try_map(move |name: &str, span: SimpleSpan| {
+            match defined.get(name) {
+                Some(&end) if end <= span.start => Ok(Operation::Var(name.to_string())),
+                _ => Err(Rich::custom(span, format!("undefined variable `{name}`"))),
+            }
+        });
+
+        let last_subtotal = just('@').map(|_| Operation::LastSubtotal);
+
+        let atom = inline_whitespace().ignore_then(
+            choice((value, call, var, last_subtotal, group)).map_with(|op, e| (op, e.span())),
+        );
+
+        // What can come before a binary operator. Operators other than `+` and `-`, which would
+        // read as the start of a line of their own, can also start a line continuing the
+        // expression above it, as long as that line has no comment.
+        let gap = |min: usize| {
+            choice((
+                inline_whitespace()
+                    .then(newline())
+                    .then(inline_whitespace())
+                    .ignored(),
+                inline_whitespace().at_least(min).ignored(),
+            ))
+        };
+
+        atom.pratt((
+            infix(left(1), gap(0).ignore_then(just('<')), binary(Operation::Less)),
+            infix(left(1), gap(0).ignore_then(just('>')), binary(Operation::Greater)),
+            infix(left(2), gap(0).ignore_then(just('|')), binary(Operation::Union)),
+            infix(left(3), gap(0).ignore_then(just('&')), binary(Operation::Intersect)),
+            infix(left(4), inline_whitespace().ignore_then(just('+')), binary(Operation::Add)),
+            infix(left(4), inline_whitespace().ignore_then(just('-')), binary(Operation::Sub)),
+            infix(left(5), gap(0).ignore_then(just('*')), binary(Operation::Mul)),
+            infix(
+                left(5),
+                gap(0).ignore_then(just('/').then_ignore(just('/').not())),
+                binary(Operation::Div),
+            ),
+            infix(left(5), gap(0).ignore_then(just("//")), binary(Operation::FloorDiv)),
+            // A `%` right after a number is a percentage, so the operator needs a space before it.
+            infix(left(5), gap(1).ignore_then(just('%')), binary(Operation::Mod)),
+            infix(right(7), gap(0).ignore_then(just('^')), binary(Operation::Pow)),
+            prefix(
+                6,
+                inline_whitespace().ignore_then(just('-')),
+                |(r, span)| (Operation::Neg(Box::new(r)), span),
+            ),
+        ))
+    })
+    .map(|(op, _)| op)
+}
+
+fn parse_subtotal<'a>(
+    locale: Locale,
+) -> impl Parser<'a, &'a str, Line<'a>, extra::Err<Rich<'a, char>>> {
+    // What the subtotal should come to can follow the rule, as in `--- 100 ± 0.5`, along with the
+    // ✓ or ✗ left there by a previous run, which gets recomputed anyway.
+    let target = || {
+        inline_whitespace()
+            .at_least(1)
+            .ignore_then(parse_value(locale))
+            .then_ignore(inline_whitespace().then(one_of("✓✗")).or_not())
+    };
+    // A name can come first, as in `--- groceries:`, to refer to the subtotal by later on. The colon
+    // keeps it apart from a one-word comment.
+    let label = || {
+        inline_whitespace()
+            .at_least(1)
+            .ignore_then(text::ident())
+            .then_ignore(just(':'))
+            .map(ToString::to_string)
+    };
+    let rule = |c: char, kind| {
+        just(c)
+            .repeated()
+            .at_least(1)
+            .to_slice()
+            .then(label().or_not())
+            .then(target().or_not())
+            // Short rules with something after them are negations, like `- 5` or `- x`.
+            .try_map(|((rule, label), target): ((&str, _), _), span| {
+                if (label.is_some() || target.is_some()) && rule.chars().count() < 3 {
+                    let message = "a rule with a label or target needs at least 3 characters";
+                    return Err(Rich::custom(span, message));
+                }
+                Ok((label, target))
+            })
+            .then_ignore(inline_whitespace().then(newline()).labelled("result line"))
+            .map(move |(label, target)| (kind, label, target))
+    };
+    // A rule of `~` averages the section instead of totalling it, and one of `|` covers it. Totals
+    // can also be ruled with `=`, which `--rule-char` can print them with.
+    let subtotal_line = choice((
+        rule('-', SubtotalKind::Total),
+        rule('=', SubtotalKind::Total),
+        rule('~', SubtotalKind::Average),
+        rule('|', SubtotalKind::Hull),
+    ));
+
+    // `total:` can stand in for the rule, with the result on the same line.
+    let total_keyword = inline_whitespace()
+        .then(just("total:"))
+        .then(inline_whitespace())
+        .to((SubtotalKind::Total, None, None));
+
+    // Comments stop short of `\r` too, so that CRLF line endings don't leak into them, and leave
+    // out trailing whitespace.
+    let comment = none_of("\r\n")
+        .ignored()
+        .repeated()
+        .to_slice()
+        .map(|c: &str| Cow::Borrowed(c.trim_end()));
+
+    let value_comment = inline_whitespace().at_least(1).ignore_then(comment.clone());
+
+    let no_value = inline_whitespace().ignore_then(comment.clone()).map(|c| (None, c));
+
+    // Values are printed right-aligned, so the result line may well start with spaces.
+    let value = inline_whitespace()
+        .ignore_then(parse_value(locale))
+        .map(Some)
+        .then(value_comment.or_not().map(|a| a.unwrap_or_default()));
+
+    let result_line = choice((value, no_value));
+    let two_lines = choice((subtotal_line, total_keyword))
+        .then(result_line)
+        .map(|((kind, label, target), (value, comment))| (kind, label, target, value, comment));
+
+    // A rule can also be followed by a comment on the same line, as in `--- net total` or
+    // `--- groceries: weekly food`, to stand for a whole subtotal like `total:` does. Anything other
+    // than a label or target after the rule is taken for a comment, which leaves the line after it
+    // alone.
+    let rule_comment = |c: char, kind| {
+        just(c)
+            .repeated()
+            .at_least(3)
+            .ignore_then(label().or_not())
+            .then_ignore(inline_whitespace().at_least(1))
+            .then(comment.clone())
+            .filter(|(_, c): &(_, Cow<str>)| !c.is_empty())
+            .map(move |(label, comment)| (kind, label, None, None, comment))
+    };
+    let one_line = choice((
+        rule_comment('-', SubtotalKind::Total),
+        rule_comment('=', SubtotalKind::Total),
+        rule_comment('~', SubtotalKind::Average),
+        rule_comment('|', SubtotalKind::Hull),
+    ));
+
+    choice((two_lines, one_line)).map_with(|(kind, label, target, v, comment), e| {
+        Line::Subtotal {
+            kind,
+            label,
+            value: v,
+            written: v,
+            target,
+            on_target: None,
+            comment,
+            span: e.span(),
+        }
+    })
+}
+
+// An operation as it appears on a line, along with its span.
+fn parse_expression<'a>(
+    locale: Locale,
+    defined: Defined,
+) -> impl Parser<'a, &'a str, Spanned<Operation>, extra::Err<Rich<'a, char>>> {
+    // A trailing operator would otherwise be swallowed into the comment, so `3 * ` quietly evaluated
+    // to `3`. Catching it here, once the operation has been parsed, lets us report it without
+    // failing the line, which would have its error overshadowed by the other kinds of line.
+    let dangling = inline_whitespace()
+        .ignore_then(
+            choice((just("//"), one_of("+-*/^|&<>%").to_slice()))
+                .map_with(|op, e| (op, e.span())),
+        )
+        .then_ignore(inline_whitespace())
+        .then_ignore(choice((newline().rewind(), end())));
+
+    parse_operation(locale, defined)
+        .map_with(|op, e| (op, e.span()))
+        .then(dangling.or_not())
+        .validate(|(value, dangling), _, emitter| {
+            if let Some((op, span)) = dangling {
+                emitter.emit(Rich::custom(span, format!("expected value after `{op}`")));
+            }
+            value
+        })
+}
+
+// The free text following an expression, if any.
+fn parse_trailing_comment<'a>(
+) -> impl Parser<'a, &'a str, Cow<'a, str>, extra::Err<Rich<'a, char>>> {
+    inline_whitespace()
+        .at_least(1)
+        .labelled("space")
+        .ignore_then(none_of("\r\n").ignored().repeated().to_slice().labelled("comment"))
+        .map(Cow::Borrowed)
+        .or_not()
+        .map(Option::unwrap_or_default)
+}
+
+fn parse_operation_line<'a>(
+    locale: Locale,
+    defined: Defined,
+) -> impl Parser<'a, &'a str, Line<'a>, extra::Err<Rich<'a, char>>> {
+    parse_expression(locale, defined)
+        .then(parse_trailing_comment())
+        .map(|((v, span), comment)| Line::Operation {
+            operation: v,
+            value: None,
+            running: None,
+            step: None,
+            comment,
+            span,
+        })
+}
+
+fn parse_assign<'a>(
+    locale: Locale,
+    defined: Defined,
+) -> impl Parser<'a, &'a str, Line<'a>, extra::Err<Rich<'a, char>>> {
+    inline_whitespace()
+        .ignore_then(text::ident())
+        .then_ignore(just('=').padded_by(inline_whitespace()))
+        .then(parse_expression(locale, defined))
+        .then(parse_trailing_comment())
+        .map(|((name, (operation, span)), comment): ((&str, _), _)| Line::Assign {
+            name: name.to_string(),
+            operation,
+            value: None,
+            comment,
+            span,
+        })
+}
+
+// The slice starts at the beginning of the line, so that indented comments print back indented.
+fn parse_comment<'a>() -> impl Parser<'a, &'a str, Line<'a>, extra::Err<Rich<'a, char>>> {
+    inline_whitespace()
+        .then(just('#'))
+        .then(none_of("\r\n").repeated())
+        .to_slice()
+        .map_with(|c: &str, e| Line::Comment(Cow::Borrowed(c), e.span()))
+}
+
+fn parse_blank<'a>() -> impl Parser<'a, &'a str, Line<'a>, extra::Err<Rich<'a, char>>> {
+    inline_whitespace()
+        .then(choice((newline().rewind(), end())))
+        .map_with(|_, e| Line::Blank(e.span()))
+}
+
+fn parse_line<'a>(
+    locale: Locale,
+    defined: Defined,
+) -> impl Parser<'a, &'a str, Line<'a>, extra::Err<Rich<'a, char>>> {
+    choice((
+        parse_comment(),
+        parse_assign(locale, defined.clone()),
+        // Before operations, which would otherwise take rules with targets for negations.
+        parse_subtotal(locale),
+        parse_operation_line(locale, defined),
+        parse_blank(),
+    ))
+}
@@ -0,0 +1,801 @@
+use std::{
+    fmt::{self, Write},
+    str::FromStr,
+};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::{Bound, Line, Locale, Operation, Step, SubtotalKind, Value};
+
+mod align;
+mod csv;
+mod json;
+mod ledger;
+mod markdown;
+mod reconcile;
+mod yaml;
+
+impl Operation {
+    fn precedence(&self) -> u8 {
+        match self {
+            Operation::Less(..) | Operation::Greater(..) => 1,
+            Operation::Union(..) => 2,
+            Operation::Intersect(..) => 3,
+            Operation::Add(..) | Operation::Sub(..) => 4,
+            Operation::Mul(..)
+            | Operation::Div(..)
+            | Operation::FloorDiv(..)
+            | Operation::Mod(..) => 5,
+            Operation::Neg(_) => 6,
+            Operation::Pow(..) => 7,
+            Operation::Call { .. }
+            | Operation::Var(_)
+            | Operation::LastSubtotal
+            | Operation::Value(..) => 8,
+        }
+    }
+}
+
+/// Output formats supported by [`pretty_print`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The document itself, with values aligned into a column.
+    #[default]
+    Text,
+    /// One `kind,expression,value,comment` row per line.
+    Csv,
+    /// An array with one object per line, holding its type, value and comment.
+    Json,
+    /// A list with one map per line, shaped like the JSON output.
+    Yaml,
+    /// A Markdown table with one row per line.
+    Markdown,
+    /// An hledger journal, with a transaction per operation and a balance assertion per subtotal.
+    /// Only documents without intervals can be rendered this way.
+    Ledger,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "yaml" => Ok(Format::Yaml),
+            "markdown" => Ok(Format::Markdown),
+            "ledger" => Ok(Format::Ledger),
+            _ => Err(format!("unknown format `{s}`")),
+        }
+    }
+}
+
+/// What to do with comments longer than [`FormatOptions::comment_width`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CommentOverflow {
+    /// Break the comment across lines, aligned under the comment column.
+    #[default]
+    Wrap,
+    /// Cut the comment short, ending it with `…`.
+    Truncate,
+}
+
+impl FromStr for CommentOverflow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "wrap" => Ok(CommentOverflow::Wrap),
+            "truncate" => Ok(CommentOverflow::Truncate),
+            _ => Err(format!("unknown comment overflow `{s}`")),
+        }
+    }
+}
+
+/// How values are lined up in the text output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    /// On their decimal points, and on both bounds for intervals.
+    #[default]
+    Decimal,
+    /// Flush right.
+    Right,
+    /// Flush left.
+    Left,
+}
+
+impl FromStr for Align {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "decimal" => Ok(Align::Decimal),
+            "right" => Ok(Align::Right),
+            "left" => Ok(Align::Left),
+            _ => Err(format!("unknown alignment `{s}`")),
+        }
+    }
+}
+
+/// Knobs controlling how [`pretty_print`] renders a document.
+#[derive(Debug, Clone)]
+pub struct FormatOptions {
+    pub format: Format,
+    /// Number of decimal places values are rounded to, up to the 28 a decimal can have.
+    pub precision: u32,
+    /// Round interval bounds away from each other so the printed interval always contains the
+    /// exact one.
+    pub round_outward: bool,
+    /// Separator inserted between groups of three integer digits, if any.
+    pub digit_separator: Option<char>,
+    /// Character the rule above each total is drawn with, `-` or `=`, the characters of the rules
+    /// that read back as totals.
+    pub rule_char: char,
+    /// Annotate intervals with their radius relative to their midpoint, as in `(±3.2%)`.
+    pub rel_width: bool,
+    /// Add a column with the running total after each operation.
+    pub running: bool,
+    /// Add a column with the share of this total each operation makes up, as a percentage.
+    pub percent_of: Option<Value>,
+    /// Maximum width of the comments following values, if any.
+    pub comment_width: Option<usize>,
+    /// How comments exceeding `comment_width` are shortened.
+    pub comment_overflow: CommentOverflow,
+    /// Number of spaces every line of text output is indented by.
+    pub indent: usize,
+    /// How values are lined up in the text output.
+    pub align: Align,
+    /// How numbers are written, which picks their decimal separator.
+    pub locale: Locale,
+    /// Round the numbers making up each total so that they add up to it as printed.
+    pub reconcile: bool,
+    /// Print comments, both after values and on lines of their own.
+    pub comments: bool,
+    /// Prefix operations with how they enter the running total: `=` when it starts from them, and
+    /// `+` or `-` after that.
+    pub show_op: bool,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            format: Format::default(),
+            precision: 2,
+            round_outward: false,
+            digit_separator: None,
+            rule_char: '-',
+            rel_width: false,
+            running: false,
+            percent_of: None,
+            comment_width: None,
+            comment_overflow: CommentOverflow::default(),
+            indent: 0,
+            align: Align::default(),
+            locale: Locale::default(),
+            reconcile: false,
+            comments: true,
+            show_op: false,
+        }
+    }
+}
+
+impl FormatOptions {
+    /// Starts building options from the defaults.
+    pub fn builder() -> FormatOptionsBuilder {
+        FormatOptionsBuilder::default()
+    }
+}
+
+/// Builds [`FormatOptions`] one knob at a time, leaving the rest at their defaults.
+#[derive(Debug, Clone, Default)]
+pub struct FormatOptionsBuilder {
+    opts: FormatOptions,
+}
+
+impl FormatOptionsBuilder {
+    pub fn format(mut self, format: Format) -> Self {
+        self.opts.format = format;
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If `precision` is over 28, more decimal places than a decimal can have.
+    pub fn precision(mut self, precision: u32) -> Self {
+        assert!(precision <= 28, "precision must be at most 28, not {precision}");
+        self.opts.precision = precision;
+        self
+    }
+
+    pub fn round_outward(mut self, round_outward: bool) -> Self {
+        self.opts.round_outward = round_outward;
+        self
+    }
+
+    pub fn digit_separator(mut self, separator: char) -> Self {
+        self.opts.digit_separator = Some(separator);
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If `rule_char` is neither `-` nor `=`, since other rules wouldn't read back as totals.
+    pub fn rule_char(mut self, rule_char: char) -> Self {
+        assert!(matches!(rule_char, '-' | '='), "rule char must be `-` or `=`, not `{rule_char}`");
+        self.opts.rule_char = rule_char;
+        self
+    }
+
+    pub fn rel_width(mut self, rel_width: bool) -> Self {
+        self.opts.rel_width = rel_width;
+        self
+    }
+
+    pub fn running(mut self, running: bool) -> Self {
+        self.opts.running = running;
+        self
+    }
+
+    pub fn percent_of(mut self, total: Value) -> Self {
+        self.opts.percent_of = Some(total);
+        self
+    }
+
+    pub fn comment_width(mut self, width: usize, overflow: CommentOverflow) -> Self {
+        self.opts.comment_width = Some(width);
+        self.opts.comment_overflow = overflow;
+        self
+    }
+
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.opts.indent = indent;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.opts.align = align;
+        self
+    }
+
+    pub fn locale(mut self, locale: Locale) -> Self {
+        self.opts.locale = locale;
+        self
+    }
+
+    pub fn reconcile(mut self, reconcile: bool) -> Self {
+        self.opts.reconcile = reconcile;
+        self
+    }
+
+    pub fn comments(mut self, comments: bool) -> Self {
+        self.opts.comments = comments;
+        self
+    }
+
+    pub fn show_op(mut self, show_op: bool) -> Self {
+        self.opts.show_op = show_op;
+        self
+    }
+
+    pub fn build(self) -> FormatOptions {
+        self.opts
+    }
+}
+
+/// Renders the value the way [`pretty_print`] does by default, rounded to 2 decimal places unless
+/// a precision is given, as in `{:.4}`.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut opts = FormatOptions::default();
+        if let Some(precision) = f.precision() {
+            opts.precision = precision as u32;
+        }
+        pretty_print_value(f, *self, &opts)
+    }
+}
+
+fn pretty_print_value(fmt: &mut impl Write, v: Value, opts: &FormatOptions) -> fmt::Result {
+    let dp = opts.precision;
+    match v {
+        Value::Number(n) => pretty_print_decimal(fmt, n.round_dp(dp), opts),
+        Value::Interval(a, b, openness) => {
+            let (lower, upper) = if opts.round_outward {
+                (
+                    RoundingStrategy::ToNegativeInfinity,
+                    RoundingStrategy::ToPositiveInfinity,
+                )
+            } else {
+                // What `round_dp` uses.
+                (
+                    RoundingStrategy::MidpointNearestEven,
+                    RoundingStrategy::MidpointNearestEven,
+                )
+            };
+
+            write!(fmt, "{}", if openness.lower { '(' } else { '[' })?;
+            pretty_print_bound(fmt, a, lower, opts)?;
+            write!(fmt, ", ")?;
+            pretty_print_bound(fmt, b, upper, opts)?;
+            write!(fmt, "{}", if openness.upper { ')' } else { ']' })
+        }
+        Value::Truth(t) => write!(fmt, "{t}"),
+    }
+}
+
+fn pretty_print_bound(
+    fmt: &mut impl Write,
+    b: Bound,
+    rounding: RoundingStrategy,
+    opts: &FormatOptions,
+) -> fmt::Result {
+    match b {
+        Bound::NegInf => write!(fmt, "-inf"),
+        Bound::Finite(d) => {
+            pretty_print_decimal(fmt, d.round_dp_with_strategy(opts.precision, rounding), opts)
+        }
+        Bound::PosInf => write!(fmt, "inf"),
+    }
+}
+
+fn pretty_print_decimal(fmt: &mut impl Write, d: Decimal, opts: &FormatOptions) -> fmt::Result {
+    // Subtractions, and rounding small negative values, can leave a zero with its sign set, which
+    // would otherwise print as `-0`.
+    let d = if d.is_zero() { Decimal::ZERO } else { d };
+    let s = d.normalize().to_string();
+    let digits = s.trim_start_matches('-');
+    let (int, frac) = digits.split_at(digits.find('.').unwrap_or(digits.len()));
+    fmt.write_str(&s[..s.len() - digits.len()])?;
+    for (i, c) in int.chars().enumerate() {
+        match opts.digit_separator {
+            Some(separator) if i > 0 && (int.len() - i) % 3 == 0 => fmt.write_char(separator)?,
+            _ => {}
+        }
+        fmt.write_char(c)?;
+    }
+    if let Some(frac) = frac.strip_prefix('.') {
+        fmt.write_char(opts.locale.decimal_separator())?;
+        fmt.write_str(frac)?;
+    }
+    Ok(())
+}
+
+fn pretty_print_operation(
+    fmt: &mut impl Write,
+    op: &Operation,
+    opts: &FormatOptions,
+) -> fmt::Result {
+    match op {
+        Operation::Add(l, r, _) => pretty_print_binary(fmt, op, l, " + ", r, true, opts),
+        Operation::Sub(l, r, _) => pretty_print_binary(fmt, op, l, " - ", r, false, opts),
+        Operation::Mul(l, r, _) => pretty_print_binary(fmt, op, l, " * ", r, true, opts),
+        Operation::Div(l, r, _) => pretty_print_binary(fmt, op, l, " / ", r, false, opts),
+        Operation::FloorDiv(l, r, _) => pretty_print_binary(fmt, op, l, " // ", r, false, opts),
+        Operation::Mod(l, r, _) => pretty_print_binary(fmt, op, l, " % ", r, false, opts),
+        Operation::Union(l, r, _) => pretty_print_binary(fmt, op, l, " | ", r, true, opts),
+        Operation::Intersect(l, r, _) => pretty_print_binary(fmt, op, l, " & ", r, true, opts),
+        Operation::Less(l, r, _) => pretty_print_binary(fmt, op, l, " < ", r, false, opts),
+        Operation::Greater(l, r, _) => pretty_print_binary(fmt, op, l, " > ", r, false, opts),
+        Operation::Pow(l, r, _) => {
+            pretty_print_operand(fmt, l, l.precedence() <= op.precedence(), opts)?;
+            write!(fmt, " ^ ")?;
+            pretty_print_operand(fmt, r, r.precedence() < op.precedence(), opts)
+        }
+        Operation::Neg(r) => {
+            write!(fmt, "-")?;
+            pretty_print_operand(fmt, r, r.precedence() < op.precedence(), opts)
+        }
+        Operation::Call { name, args } => {
+            write!(fmt, "{}(", name.name())?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(fmt, ", ")?;
+                }
+                pretty_print_operation(fmt, arg, opts)?;
+            }
+            write!(fmt, ")")
+        }
+        Operation::Var(name) => write!(fmt, "{name}"),
+        Operation::LastSubtotal => write!(fmt, "@"),
+        Operation::Value(v, _) => pretty_print_value(fmt, *v, opts),
+    }
+}
+
+// Only parenthesize operands that would otherwise re-parse differently: anything binding looser
+// than `op`, plus equal-precedence right operands unless they're the same associative operation,
+// as in `a - (b - c)` or `a * (b // c)`.
+fn pretty_print_binary(
+    fmt: &mut impl Write,
+    op: &Operation,
+    l: &Operation,
+    symbol: &str,
+    r: &Operation,
+    associative: bool,
+    opts: &FormatOptions,
+) -> fmt::Result {
+    let prec = op.precedence();
+    pretty_print_operand(fmt, l, l.precedence() < prec, opts)?;
+    write!(fmt, "{symbol}")?;
+    pretty_print_operand(
+        fmt,
+        r,
+        r.precedence() < prec
+            || (r.precedence() == prec
+                && !(associative && std::mem::discriminant(r) == std::mem::discriminant(op))),
+        opts,
+    )
+}
+
+fn pretty_print_operand(
+    fmt: &mut impl Write,
+    op: &Operation,
+    parens: bool,
+    opts: &FormatOptions,
+) -> fmt::Result {
+    if parens {
+        write!(fmt, "(")?;
+        pretty_print_operation(fmt, op, opts)?;
+        write!(fmt, ")")
+    } else {
+        pretty_print_operation(fmt, op, opts)
+    }
+}
+
+fn value_string(v: Value, opts: &FormatOptions) -> Result<String, fmt::Error> {
+    let mut out = String::new();
+    pretty_print_value(&mut out, v, opts)?;
+    Ok(out)
+}
+
+fn operation_string(op: &Operation, opts: &FormatOptions) -> Result<String, fmt::Error> {
+    let mut out = String::new();
+    pretty_print_operation(&mut out, op, opts)?;
+    Ok(out)
+}
+
+/// Renders an evaluated document in the format selected by `opts`.
+pub fn pretty_print(
+    mut lines: Vec<Line>,
+    opts: &FormatOptions,
+) -> Result<String, std::fmt::Error> {
+    if !opts.comments {
+        lines.retain(|line| !matches!(line, Line::Comment(..)));
+        for line in &mut lines {
+            match line {
+                Line::Operation { comment, .. }
+                | Line::Subtotal { comment, .. }
+                | Line::Assign { comment, .. } => *comment = "".into(),
+                Line::Comment(..) | Line::Blank(_) => {}
+            }
+        }
+    }
+    // JSON and YAML keep values exact, so there's no rounding to reconcile.
+    if opts.reconcile && !matches!(opts.format, Format::Json | Format::Yaml) {
+        let entries = entries(&lines);
+        reconcile::reconcile(&mut lines, &entries, opts.precision);
+    }
+    match opts.format {
+        Format::Text => print_text(lines, opts),
+        Format::Csv => csv::print_csv(&lines, opts),
+        Format::Json => json::print_json(&lines),
+        Format::Yaml => yaml::print_yaml(&lines),
+        Format::Markdown => markdown::print_markdown(&lines, opts),
+        Format::Ledger => ledger::print_ledger(&lines, opts),
+    }
+}
+
+// Targets are written back exactly, and as `mid ± radius` when they're bounded intervals, so that
+// they read back the same.
+fn target_string(target: Value, opts: &FormatOptions) -> Result<String, fmt::Error> {
+    let exact = FormatOptions {
+        precision: 28,
+        round_outward: false,
+        digit_separator: None,
+        ..opts.clone()
+    };
+    let mut out = String::new();
+    match (target, target.mid(), target.radius()) {
+        (Value::Interval(..), Ok(Value::Number(mid)), Ok(Value::Number(radius))) => {
+            pretty_print_decimal(&mut out, mid, &exact)?;
+            write!(&mut out, " ± ")?;
+            pretty_print_decimal(&mut out, radius, &exact)?;
+        }
+        _ => pretty_print_value(&mut out, target, &exact)?,
+    }
+    Ok(out)
+}
+
+/// How an operation entered the running total.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    /// The running total started over from it, at the top of the document or of a section.
+    Start,
+    Add,
+    Sub,
+}
+
+impl Entry {
+    fn symbol(self) -> char {
+        match self {
+            Entry::Start => '=',
+            Entry::Add => '+',
+            Entry::Sub => '-',
+        }
+    }
+}
+
+// How each operation of an evaluated document entered the running total, as recorded by the
+// evaluator.
+fn entries(lines: &[Line]) -> Vec<Option<Entry>> {
+    lines
+        .iter()
+        .map(|line| match line {
+            Line::Operation {
+                step: Some(step), ..
+            } => Some(match step {
+                Step::Start(_) => Entry::Start,
+                Step::Add { .. } => Entry::Add,
+                Step::Sub { .. } => Entry::Sub,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn column_width(column: &[Option<String>]) -> usize {
+    column
+        .iter()
+        .map(|l| l.as_ref().map(|l| l.chars().count()).unwrap_or(0))
+        .max()
+        .unwrap_or(0)
+}
+
+// Renders the value picked out of each line by `value`, aligned on the decimal point.
+fn value_column(
+    lines: &[Line],
+    opts: &FormatOptions,
+    value: impl Fn(&Line) -> Option<Value>,
+) -> Result<Vec<Option<String>>, fmt::Error> {
+    let column: Vec<_> = lines
+        .iter()
+        .map(|line| value(line).map(|v| value_string(v, opts)).transpose())
+        .collect::<Result<_, _>>()?;
+    Ok(align::align_decimals(&column, opts.locale.decimal_separator()))
+}
+
+// `v` as a percentage of `total`, unless `total` can't be divided by or `v` isn't an amount.
+fn percent(v: Value, total: Value) -> Option<Value> {
+    match (v, total) {
+        (Value::Truth(_), _) => None,
+        (_, Value::Number(t)) if !t.is_zero() => {
+            v.mul(Value::Number(Decimal::ONE_HUNDRED))
+                .and_then(|v| v.div(total))
+                .ok()
+        }
+        _ => None,
+    }
+}
+
+// How wide an interval is relative to its midpoint, or `n/a` when that is zero or unbounded.
+fn relative_width(v: Value) -> String {
+    match (v.mid(), v.radius()) {
+        (Ok(Value::Number(mid)), Ok(Value::Number(radius))) if !mid.is_zero() => {
+            let percent = radius
+                .checked_div(mid.abs())
+                .and_then(|r| r.checked_mul(Decimal::ONE_HUNDRED));
+            match percent {
+                Some(percent) => format!("(±{:.1}%)", percent.round_dp(1)),
+                None => "(n/a)".to_string(),
+            }
+        }
+        _ => "(n/a)".to_string(),
+    }
+}
+
+// Shortens `comment` to fit `opts.comment_width`, returning the lines it now takes up.
+fn fit_comment(comment: &str, opts: &FormatOptions) -> Vec<String> {
+    let width = match opts.comment_width {
+        Some(width) if comment.chars().count() > width => width.max(1),
+        _ => return vec![comment.to_string()],
+    };
+
+    match opts.comment_overflow {
+        CommentOverflow::Truncate => {
+            let kept: String = comment.chars().take(width - 1).collect();
+            vec![format!("{kept}…")]
+        }
+        CommentOverflow::Wrap => {
+            let mut lines = Vec::new();
+            let mut line = String::new();
+            for mut word in comment.split_whitespace() {
+                // Words too long for any line are broken up wherever they hit the edge.
+                while word.chars().count() > width {
+                    if !line.is_empty() {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    let (at, _) = word.char_indices().nth(width).unwrap();
+                    lines.push(word[..at].to_string());
+                    word = &word[at..];
+                }
+
+                if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                    lines.push(std::mem::take(&mut line));
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+            }
+            if !line.is_empty() || lines.is_empty() {
+                lines.push(line);
+            }
+            lines
+        }
+    }
+}
+
+fn print_text(lines: Vec<Line>, opts: &FormatOptions) -> Result<String, std::fmt::Error> {
+    let lhs: Vec<_> = lines
+        .iter()
+        .map(|line| match line {
+            Line::Operation { operation, .. } => operation_string(operation, opts).map(Some),
+            Line::Subtotal { value, .. } => value.map(|v| value_string(v, opts)).transpose(),
+            Line::Assign { .. } | Line::Comment(..) | Line::Blank(_) => Ok(None),
+        })
+        .collect::<Result<_, _>>()?;
+    let mut lhs = match opts.align {
+        Align::Decimal => align::align_decimals(&lhs, opts.locale.decimal_separator()),
+        Align::Right | Align::Left => lhs,
+    };
+    if opts.show_op {
+        for ((lhs, line), entry) in lhs.iter_mut().zip(&lines).zip(entries(&lines)) {
+            // Subtotals are padded to stay aligned with the operations above them.
+            if let (Some(lhs), Line::Operation { .. } | Line::Subtotal { .. }) = (lhs, line) {
+                *lhs = format!("{} {lhs}", entry.map_or(' ', Entry::symbol));
+            }
+        }
+    }
+
+    let lhs_col = column_width(&lhs);
+    // Values are right-aligned when laid out, so others need their padding now.
+    if opts.align == Align::Left {
+        for lhs in lhs.iter_mut().flatten() {
+            *lhs = format!("{lhs:<lhs_col$}");
+        }
+    }
+
+    // Optional columns, going between the values and the comments.
+    let mut extra = Vec::new();
+    if opts.rel_width {
+        extra.push(
+            lines
+                .iter()
+                .map(|line| match line {
+                    Line::Operation {
+                        value: Some(v @ Value::Interval(..)),
+                        ..
+                    }
+                    | Line::Subtotal {
+                        value: Some(v @ Value::Interval(..)),
+                        ..
+                    } => Some(relative_width(*v)),
+                    _ => None,
+                })
+                .collect(),
+        );
+    }
+    if opts.running {
+        extra.push(value_column(&lines, opts, |line| match line {
+            Line::Operation { running, .. } => *running,
+            _ => None,
+        })?);
+    }
+    if let Some(total) = opts.percent_of {
+        let percent_opts = FormatOptions {
+            precision: 1,
+            ..opts.clone()
+        };
+        let column = value_column(&lines, &percent_opts, |line| match line {
+            Line::Operation { value: Some(v), .. } => percent(*v, total),
+            _ => None,
+        })?;
+        extra.push(column.into_iter().map(|p| p.map(|p| p + "%")).collect());
+    }
+    let extra_cols: Vec<_> = extra.iter().map(|column| column_width(column)).collect();
+
+    let mut s = String::new();
+    for (i, (lhs, line)) in lhs.into_iter().zip(lines).enumerate() {
+        let mut extras = String::new();
+        for (column, &width) in extra.iter().zip(&extra_cols) {
+            let cell = column[i].as_deref().unwrap_or("");
+            write!(&mut extras, "{cell:>width$} ")?;
+        }
+        // Where continuation lines of wrapped comments start.
+        let indent = lhs_col + 1 + extras.chars().count();
+
+        match line {
+            Line::Operation { comment, .. } => {
+                let comment = fit_comment(&comment, opts);
+                writeln!(
+                    &mut s,
+                    "{:>width$} {extras}{}",
+                    lhs.unwrap_or_default(),
+                    comment[0],
+                    width = lhs_col
+                )?;
+                for line in &comment[1..] {
+                    writeln!(&mut s, "{:indent$}{line}", "")?;
+                }
+            }
+            Line::Subtotal {
+                kind,
+                label,
+                target,
+                on_target,
+                comment,
+                ..
+            } => {
+                let rule_char = match kind {
+                    SubtotalKind::Total => opts.rule_char,
+                    SubtotalKind::Average => '~',
+                    SubtotalKind::Hull => '|',
+                };
+                // Shorter rules followed by anything would read back as negations.
+                let width = if label.is_some() || target.is_some() {
+                    lhs_col.max(3)
+                } else {
+                    lhs_col
+                };
+                let mut rule: String = std::iter::repeat_n(rule_char, width).collect();
+                if let Some(label) = label {
+                    write!(&mut rule, " {label}:")?;
+                }
+                match target {
+                    Some(target) => {
+                        let mark = match on_target {
+                            Some(true) => " ✓",
+                            Some(false) => " ✗",
+                            None => "",
+                        };
+                        writeln!(&mut s, "{rule} {}{mark}", target_string(target, opts)?)?;
+                    }
+                    None => writeln!(&mut s, "{rule}")?,
+                }
+
+                let comment = fit_comment(&comment, opts);
+                let lhs = lhs.unwrap_or_default();
+                writeln!(&mut s, "{:>width$} {extras}{}", lhs, comment[0], width = lhs_col)?;
+                for line in &comment[1..] {
+                    writeln!(&mut s, "{:indent$}{line}", "")?;
+                }
+            }
+            Line::Assign {
+                name,
+                operation,
+                comment,
+                ..
+            } => {
+                let operation = operation_string(&operation, opts)?;
+                writeln!(&mut s, "{name} = {operation} {comment}")?;
+            }
+            Line::Comment(comment, _) => writeln!(&mut s, "{comment}")?,
+            Line::Blank(_) => writeln!(&mut s)?,
+        }
+    }
+
+    if opts.indent == 0 && opts.comments {
+        return Ok(s);
+    }
+    // Indenting whole lines once they're laid out keeps them aligned with each other.
+    let indent = " ".repeat(opts.indent);
+    let mut indented = String::new();
+    for line in s.lines() {
+        // Without comments, lines would end with the space meant to come before them.
+        let line = if opts.comments { line } else { line.trim_end() };
+        if !line.is_empty() {
+            indented.push_str(&indent);
+        }
+        writeln!(&mut indented, "{line}")?;
+    }
+    Ok(indented)
+}
@@ -0,0 +1,83 @@
+/// Pads a column of rendered values so that their decimal points line up, returning strings of
+/// equal width.
+///
+/// Each value is split into alternating right- and left-aligned pieces around its decimal points:
+/// `[int, frac]` for numbers and expressions (using their last number), and
+/// `[int, frac, int, frac]` for interval literals so that both bounds line up. Pieces are matched
+/// up from the right, so plain numbers align with the upper bound of intervals.
+pub(super) fn align_decimals(column: &[Option<String>], point: char) -> Vec<Option<String>> {
+    let pieces: Vec<Option<Vec<&str>>> = column
+        .iter()
+        .map(|s| s.as_deref().map(|s| split_pieces(s, point)))
+        .collect();
+
+    let cols = pieces.iter().flatten().map(Vec::len).max().unwrap_or(0);
+    let mut widths = vec![0; cols];
+    for p in pieces.iter().flatten() {
+        let offset = cols - p.len();
+        for (i, piece) in p.iter().enumerate().skip(1) {
+            widths[offset + i] = widths[offset + i].max(width(piece));
+        }
+    }
+    // The first piece of a value may span every column to its left that it has no piece for.
+    for p in pieces.iter().flatten() {
+        let span: usize = widths[..=cols - p.len()].iter().sum();
+        widths[0] += width(p[0]).saturating_sub(span);
+    }
+
+    pieces
+        .iter()
+        .map(|p| {
+            p.as_ref().map(|p| {
+                let offset = cols - p.len();
+                let span: usize = widths[..=offset].iter().sum();
+                let mut out = format!("{:>span$}", p[0]);
+                for (i, piece) in p.iter().enumerate().skip(1) {
+                    let w = widths[offset + i];
+                    if (offset + i) % 2 == 0 {
+                        out.push_str(&format!("{piece:>w$}"));
+                    } else {
+                        out.push_str(&format!("{piece:<w$}"));
+                    }
+                }
+                out
+            })
+        })
+        .collect()
+}
+
+fn split_pieces(s: &str, point: char) -> Vec<&str> {
+    if s.starts_with(['[', '(']) && s.ends_with([']', ')']) && s.matches(' ').count() == 1 {
+        if let Some(comma) = s.find(", ") {
+            let (lower, upper) = s.split_at(comma + 2);
+            let (lower_int, _) = split_decimal(&lower[..comma], point);
+            let (upper_int, upper_frac) = split_decimal(upper, point);
+            return vec![lower_int, &lower[lower_int.len()..], upper_int, upper_frac];
+        }
+    }
+
+    let (int, frac) = split_decimal(s, point);
+    vec![int, frac]
+}
+
+// Splits `s` at the decimal `point` of its last number, or right after that number if it has none.
+fn split_decimal(s: &str, point: char) -> (&str, &str) {
+    let Some(last_digit) = s.rfind(|c: char| c.is_ascii_digit()) else {
+        return (s, "");
+    };
+    let end = last_digit + 1;
+    let start = s[..end]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !(c.is_ascii_digit() || *c == point))
+        .map_or(0, |(i, c)| i + c.len_utf8());
+
+    match s[start..end].find(point) {
+        Some(dot) => s.split_at(start + dot),
+        None => s.split_at(end),
+    }
+}
+
+fn width(s: &str) -> usize {
+    s.chars().count()
+}
@@ -0,0 +1,64 @@
+use std::fmt::{self, Write};
+
+use super::{operation_string, value_string, FormatOptions};
+use crate::Line;
+
+pub(super) fn print_csv(lines: &[Line], opts: &FormatOptions) -> Result<String, fmt::Error> {
+    let mut s = String::new();
+    writeln!(&mut s, "kind,expression,value,comment")?;
+
+    for line in lines {
+        let (kind, expression, value, comment) = match line {
+            Line::Operation {
+                operation,
+                value,
+                comment,
+                ..
+            } => (
+                "operation",
+                operation_string(operation, opts)?,
+                *value,
+                comment.as_ref(),
+            ),
+            Line::Subtotal { value, comment, .. } => {
+                ("subtotal", String::new(), *value, comment.as_ref())
+            }
+            Line::Assign {
+                name,
+                operation,
+                value,
+                comment,
+                ..
+            } => (
+                "assign",
+                format!("{name} = {}", operation_string(operation, opts)?),
+                *value,
+                comment.as_ref(),
+            ),
+            Line::Comment(comment, _) => ("comment", String::new(), None, comment.as_ref()),
+            Line::Blank(_) => ("blank", String::new(), None, ""),
+        };
+        let value = match value {
+            Some(v) => value_string(v, opts)?,
+            None => String::new(),
+        };
+
+        writeln!(
+            &mut s,
+            "{kind},{},{},{}",
+            field(&expression),
+            field(&value),
+            field(comment)
+        )?;
+    }
+    Ok(s)
+}
+
+// Quote fields that would otherwise be split or misread, which includes every interval.
+fn field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
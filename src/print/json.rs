@@ -0,0 +1,55 @@
+use std::fmt::{self, Write};
+
+use crate::{Line, Value};
+
+// Decimals are emitted as strings so consumers don't round-trip them through floats.
+pub(super) fn print_json(lines: &[Line]) -> Result<String, fmt::Error> {
+    let mut s = String::new();
+    writeln!(&mut s, "[")?;
+
+    for (i, line) in lines.iter().enumerate() {
+        let (kind, value, comment) = match line {
+            Line::Operation { value, comment, .. } => ("operation", *value, comment.as_ref()),
+            Line::Subtotal { value, comment, .. } => ("subtotal", *value, comment.as_ref()),
+            Line::Assign { value, comment, .. } => ("assign", *value, comment.as_ref()),
+            Line::Comment(comment, _) => ("comment", None, comment.as_ref()),
+            Line::Blank(_) => ("blank", None, ""),
+        };
+
+        write!(&mut s, "  {{\"type\": \"{kind}\", \"value\": ")?;
+        match value {
+            Some(Value::Number(n)) => write!(&mut s, "\"{}\"", n.normalize())?,
+            Some(Value::Interval(a, b, openness)) => write!(
+                &mut s,
+                "{{\"low\": \"{a}\", \"high\": \"{b}\", \"low_open\": {}, \"high_open\": {}}}",
+                openness.lower, openness.upper
+            )?,
+            Some(Value::Truth(t)) => write!(&mut s, "\"{t}\"")?,
+            None => write!(&mut s, "null")?,
+        }
+        write!(&mut s, ", \"comment\": ")?;
+        write_string(&mut s, comment)?;
+
+        let separator = if i + 1 < lines.len() { "," } else { "" };
+        writeln!(&mut s, "}}{separator}")?;
+    }
+
+    writeln!(&mut s, "]")?;
+    Ok(s)
+}
+
+pub(super) fn write_string(fmt: &mut impl Write, s: &str) -> fmt::Result {
+    fmt.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => fmt.write_str("\\\"")?,
+            '\\' => fmt.write_str("\\\\")?,
+            '\n' => fmt.write_str("\\n")?,
+            '\r' => fmt.write_str("\\r")?,
+            '\t' => fmt.write_str("\\t")?,
+            c if c.is_control() => write!(fmt, "\\u{:04x}", c as u32)?,
+            c => fmt.write_char(c)?,
+        }
+    }
+    fmt.write_char('"')
+}
@@ -0,0 +1,63 @@
+use std::fmt::{self, Write};
+
+use super::{operation_string, value_string, FormatOptions};
+use crate::{Line, SubtotalKind, Value};
+
+// Journals need dates, which documents don't have.
+const DATE: &str = "1970-01-01";
+const ACCOUNT: &str = "calc";
+const BALANCING_ACCOUNT: &str = "equity";
+
+// Each posting moves the account by however much the running total changed, so that its balance
+// always matches the running total and every subtotal can be asserted against it.
+pub(super) fn print_ledger(lines: &[Line], opts: &FormatOptions) -> Result<String, fmt::Error> {
+    let mut s = String::new();
+    let mut balance = Value::Number(0.into());
+
+    for line in lines {
+        match line {
+            Line::Operation {
+                operation,
+                running: Some(running),
+                comment,
+                ..
+            } => {
+                let amount = running.sub(balance).map_err(|_| fmt::Error)?;
+                balance = *running;
+
+                let description = if comment.is_empty() {
+                    operation_string(operation, opts)?
+                } else {
+                    comment.to_string()
+                };
+                writeln!(&mut s, "{DATE} {description}")?;
+                writeln!(&mut s, "    {ACCOUNT}  {}", amount_string(amount, opts)?)?;
+                writeln!(&mut s, "    {BALANCING_ACCOUNT}")?;
+                writeln!(&mut s)?;
+            }
+            // Averages aren't balances, so there's nothing to assert.
+            Line::Subtotal {
+                kind: SubtotalKind::Total,
+                value: Some(value),
+                comment,
+                ..
+            } => {
+                writeln!(&mut s, "{DATE} {comment}")?;
+                writeln!(&mut s, "    {ACCOUNT}  0 = {}", amount_string(*value, opts)?)?;
+                writeln!(&mut s)?;
+            }
+            Line::Comment(comment, _) => {
+                writeln!(&mut s, ";{}", comment.trim_start().trim_start_matches('#'))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(s)
+}
+
+fn amount_string(v: Value, opts: &FormatOptions) -> Result<String, fmt::Error> {
+    match v {
+        Value::Number(_) => value_string(v, opts),
+        Value::Interval(..) | Value::Truth(_) => Err(fmt::Error),
+    }
+}
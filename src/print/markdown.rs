@@ -0,0 +1,58 @@
+use std::fmt::{self, Write};
+
+use super::{operation_string, value_string, FormatOptions};
+use crate::Line;
+
+pub(super) fn print_markdown(lines: &[Line], opts: &FormatOptions) -> Result<String, fmt::Error> {
+    let mut s = String::new();
+    writeln!(&mut s, "| Expression | Value | Comment |")?;
+    writeln!(&mut s, "| ---: | ---: | --- |")?;
+
+    // Blank lines would end the table.
+    for line in lines.iter().filter(|line| !matches!(line, Line::Blank(_))) {
+        let (expression, value, comment) = match line {
+            Line::Operation {
+                operation,
+                value,
+                comment,
+                ..
+            } => (operation_string(operation, opts)?, *value, comment),
+            Line::Subtotal { value, comment, .. } => (String::new(), *value, comment),
+            Line::Assign {
+                name,
+                operation,
+                value,
+                comment,
+                ..
+            } => (
+                format!("{name} = {}", operation_string(operation, opts)?),
+                *value,
+                comment,
+            ),
+            Line::Comment(comment, _) => (String::new(), None, comment),
+            Line::Blank(_) => unreachable!(),
+        };
+        let value = match value {
+            Some(v) => value_string(v, opts)?,
+            None => String::new(),
+        };
+
+        writeln!(
+            &mut s,
+            "| {} | {} | {} |",
+            cell(&expression),
+            cell(&value),
+            cell(comment)
+        )?;
+        // Tables can't hold a real rule, so subtotals are set apart by a row of dashes instead.
+        if let Line::Subtotal { .. } = line {
+            writeln!(&mut s, "| --- | --- | --- |")?;
+        }
+    }
+    Ok(s)
+}
+
+// Pipes would otherwise end the cell, and that includes the union operator.
+fn cell(s: &str) -> String {
+    s.replace('|', "\\|")
+}
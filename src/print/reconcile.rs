@@ -0,0 +1,132 @@
+use rust_decimal::{prelude::ToPrimitive, Decimal, RoundingStrategy};
+
+use super::Entry;
+use crate::{Line, Operation, SubtotalKind, Value};
+
+/// Rounds the numbers making up each total of an evaluated document to `dp` decimal places so that,
+/// once printed, they add up to the printed total.
+///
+/// Only totals made up of plain numbers are reconciled, as the values of expressions and intervals
+/// aren't printed. Numbers are rounded as usual unless that leaves the total off, in which case the
+/// difference is made up with the largest remainder method: every number is rounded down, and the
+/// ones that lost the most are rounded up instead until the total is met.
+pub(super) fn reconcile(lines: &mut [Line], entries: &[Option<Entry>], dp: u32) {
+    // The operations since the last total along with the sign they enter the running total with,
+    // or `None` once one of them can't be rounded. A document that doesn't start with its running
+    // total, as when sliced, has nothing to carry on from until its first total.
+    let mut terms = None;
+    // The last total as printed, which the running total carries on from.
+    let mut base = Decimal::ZERO;
+    for i in 0..lines.len() {
+        match (&lines[i], entries[i]) {
+            (
+                Line::Operation {
+                    operation,
+                    value: Some(Value::Number(_)),
+                    running: Some(Value::Number(_)),
+                    ..
+                },
+                Some(entry),
+            ) => {
+                let sign = match entry {
+                    Entry::Start => {
+                        terms = Some(Vec::new());
+                        base = Decimal::ZERO;
+                        Decimal::ONE
+                    }
+                    Entry::Add => Decimal::ONE,
+                    Entry::Sub => Decimal::NEGATIVE_ONE,
+                };
+                match (&mut terms, operation) {
+                    (Some(terms), Operation::Value(..)) => terms.push((i, sign)),
+                    _ => terms = None,
+                }
+            }
+            // Comparisons don't take part in the running total.
+            (
+                Line::Operation {
+                    value: Some(Value::Truth(_)),
+                    ..
+                },
+                _,
+            ) => {}
+            (Line::Operation { .. }, _) => terms = None,
+            (
+                Line::Subtotal {
+                    kind: SubtotalKind::Total,
+                    value,
+                    ..
+                },
+                _,
+            ) => {
+                let total = match value {
+                    Some(Value::Number(total)) => Some(total.round_dp(dp)),
+                    _ => None,
+                };
+                if let (Some(terms), Some(total)) = (terms.take(), total) {
+                    distribute(lines, &terms, total - base, dp);
+                }
+                base = total.unwrap_or_default();
+                terms = Some(Vec::new());
+            }
+            (
+                Line::Subtotal { .. } | Line::Assign { .. } | Line::Comment(..) | Line::Blank(_),
+                _,
+            ) => {}
+        }
+    }
+}
+
+// Rounds the operations at `terms` so that their signed sum is `target`.
+fn distribute(lines: &mut [Line], terms: &[(usize, Decimal)], target: Decimal, dp: u32) {
+    let contributions: Vec<Decimal> = terms
+        .iter()
+        .map(|&(i, sign)| match &lines[i] {
+            Line::Operation {
+                value: Some(Value::Number(v)),
+                ..
+            } => sign * v,
+            _ => unreachable!("only numbers are reconciled"),
+        })
+        .collect();
+    if contributions
+        .iter()
+        .map(|c| c.round_dp(dp))
+        .sum::<Decimal>()
+        == target
+    {
+        return;
+    }
+
+    let floors: Vec<Decimal> = contributions
+        .iter()
+        .map(|c| c.round_dp_with_strategy(dp, RoundingStrategy::ToNegativeInfinity))
+        .collect();
+    let ulp = Decimal::new(1, dp);
+    let short = ((target - floors.iter().sum::<Decimal>()) / ulp)
+        .clamp(Decimal::ZERO, terms.len().into())
+        .to_usize()
+        .unwrap_or(0);
+
+    // Ties go to the earlier lines, as the sort is stable.
+    let mut order: Vec<usize> = (0..terms.len()).collect();
+    order.sort_by_key(|&j| std::cmp::Reverse(contributions[j] - floors[j]));
+    for (rank, j) in order.into_iter().enumerate() {
+        let rounded = if rank < short {
+            floors[j] + ulp
+        } else {
+            floors[j]
+        };
+        let (i, sign) = terms[j];
+        if let Line::Operation {
+            operation, value, ..
+        } = &mut lines[i]
+        {
+            let n = Value::Number(sign * rounded);
+            if let Operation::Value(v, _) = operation {
+                *v = n;
+            }
+            *value = Some(n);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::fmt::{self, Write};
+
+use super::json::write_string;
+use crate::{Line, Value};
+
+// The same structure as the JSON output. Strings are double-quoted, which YAML escapes the same way
+// JSON does, and decimals are strings so that they aren't read back as floats.
+pub(super) fn print_yaml(lines: &[Line]) -> Result<String, fmt::Error> {
+    let mut s = String::new();
+    if lines.is_empty() {
+        writeln!(&mut s, "[]")?;
+        return Ok(s);
+    }
+
+    for line in lines {
+        let (kind, value, comment) = match line {
+            Line::Operation { value, comment, .. } => ("operation", *value, comment.as_ref()),
+            Line::Subtotal { value, comment, .. } => ("subtotal", *value, comment.as_ref()),
+            Line::Assign { value, comment, .. } => ("assign", *value, comment.as_ref()),
+            Line::Comment(comment, _) => ("comment", None, comment.as_ref()),
+            Line::Blank(_) => ("blank", None, ""),
+        };
+
+        writeln!(&mut s, "- type: {kind}")?;
+        match value {
+            Some(Value::Number(n)) => writeln!(&mut s, "  value: \"{}\"", n.normalize())?,
+            Some(Value::Interval(a, b, openness)) => {
+                writeln!(&mut s, "  value:")?;
+                writeln!(&mut s, "    low: \"{a}\"")?;
+                writeln!(&mut s, "    high: \"{b}\"")?;
+                writeln!(&mut s, "    low_open: {}", openness.lower)?;
+                writeln!(&mut s, "    high_open: {}", openness.upper)?;
+            }
+            Some(Value::Truth(t)) => writeln!(&mut s, "  value: \"{t}\"")?,
+            None => writeln!(&mut s, "  value: null")?,
+        }
+        write!(&mut s, "  comment: ")?;
+        write_string(&mut s, comment)?;
+        writeln!(&mut s)?;
+    }
+    Ok(s)
+}
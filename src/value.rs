@@ -0,0 +1,487 @@
+use std::{cmp::Ordering, fmt};
+
+use rust_decimal::{prelude::ToPrimitive, Decimal, MathematicalOps, RoundingStrategy};
+
+use crate::EvalError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Value {
+    Number(Decimal),
+    Interval(Bound, Bound, Openness),
+    /// The outcome of a comparison, which can't take part in arithmetic.
+    Truth(Truth),
+}
+
+/// The result of comparing two values, which may be undecidable when intervals overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Truth {
+    True,
+    False,
+    Indeterminate,
+}
+
+impl fmt::Display for Truth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Truth::True => write!(f, "true"),
+            Truth::False => write!(f, "false"),
+            Truth::Indeterminate => write!(f, "indeterminate"),
+        }
+    }
+}
+
+/// Which ends of an interval are open, leaving out their bound, as in `[0, 1)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Openness {
+    pub lower: bool,
+    pub upper: bool,
+}
+
+impl Openness {
+    pub const CLOSED: Openness = Openness {
+        lower: false,
+        upper: false,
+    };
+}
+
+/// An interval endpoint, which unlike a plain number may be infinite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Bound {
+    NegInf,
+    Finite(Decimal),
+    PosInf,
+}
+
+impl From<Decimal> for Bound {
+    fn from(d: Decimal) -> Self {
+        Bound::Finite(d)
+    }
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::NegInf => write!(f, "-inf"),
+            Bound::Finite(d) => write!(f, "{}", d.normalize()),
+            Bound::PosInf => write!(f, "inf"),
+        }
+    }
+}
+
+impl Bound {
+    const ZERO: Bound = Bound::Finite(Decimal::ZERO);
+
+    fn add(self, other: Bound) -> Result<Bound, EvalError> {
+        match (self, other) {
+            (Bound::Finite(a), Bound::Finite(b)) => checked(a.checked_add(b)).map(Bound::Finite),
+            (Bound::PosInf, Bound::NegInf) | (Bound::NegInf, Bound::PosInf) => {
+                Err(EvalError::undefined("inf - inf"))
+            }
+            (Bound::Finite(_), inf) | (inf, _) => Ok(inf),
+        }
+    }
+
+    fn neg(self) -> Bound {
+        match self {
+            Bound::NegInf => Bound::PosInf,
+            Bound::Finite(d) => Bound::Finite(-d),
+            Bound::PosInf => Bound::NegInf,
+        }
+    }
+
+    fn mul(self, other: Bound) -> Result<Bound, EvalError> {
+        match (self, other) {
+            (Bound::Finite(a), Bound::Finite(b)) => checked(a.checked_mul(b)).map(Bound::Finite),
+            // Infinite bounds are never reached, so multiplying them by zero only ever gives zero.
+            (Bound::Finite(z), _) | (_, Bound::Finite(z)) if z.is_zero() => Ok(Bound::ZERO),
+            (a, b) if (a < Bound::ZERO) == (b < Bound::ZERO) => Ok(Bound::PosInf),
+            _ => Ok(Bound::NegInf),
+        }
+    }
+
+    // Callers make sure the bound isn't zero.
+    fn recip(self) -> Result<Bound, EvalError> {
+        match self {
+            Bound::Finite(d) => checked(Decimal::ONE.checked_div(d)).map(Bound::Finite),
+            Bound::NegInf | Bound::PosInf => Ok(Bound::ZERO),
+        }
+    }
+
+    fn abs(self) -> Bound {
+        if self < Bound::ZERO {
+            self.neg()
+        } else {
+            self
+        }
+    }
+
+    // Callers make sure the bound isn't negative.
+    fn sqrt(self) -> Bound {
+        match self {
+            Bound::Finite(d) => Bound::Finite(d.sqrt().unwrap_or_default()),
+            inf => inf,
+        }
+    }
+
+    fn round(self, dp: u32, strategy: RoundingStrategy) -> Bound {
+        match self {
+            Bound::Finite(d) => Bound::Finite(d.round_dp_with_strategy(dp, strategy)),
+            inf => inf,
+        }
+    }
+
+    fn powi(self, exp: i64) -> Result<Bound, EvalError> {
+        match self {
+            Bound::Finite(d) => checked(d.checked_powi(exp)).map(Bound::Finite),
+            _ if exp == 0 => Ok(Bound::Finite(Decimal::ONE)),
+            Bound::NegInf if exp % 2 != 0 => Ok(Bound::NegInf),
+            _ => Ok(Bound::PosInf),
+        }
+    }
+}
+
+// Decimals panic when they overflow unless their checked operations are used, which this turns
+// into an error.
+fn checked(d: Option<Decimal>) -> Result<Decimal, EvalError> {
+    d.ok_or(EvalError::Overflow)
+}
+
+impl Value {
+    /// Builds an interval from two endpoints given in either order.
+    pub fn interval(a: impl Into<Bound>, b: impl Into<Bound>) -> Value {
+        let (a, b) = (a.into(), b.into());
+        Value::Interval(a.min(b), a.max(b), Openness::CLOSED)
+    }
+
+    /// Which ends of this value are open, with numbers being closed on both.
+    fn openness(self) -> Openness {
+        match self {
+            Value::Interval(_, _, openness) => openness,
+            _ => Openness::CLOSED,
+        }
+    }
+
+    /// The endpoints of this value, treating a number as a single-point interval.
+    fn bounds(self) -> (Bound, Bound) {
+        match self {
+            Value::Number(n) => (Bound::Finite(n), Bound::Finite(n)),
+            Value::Interval(a, b, _) => (a, b),
+            Value::Truth(_) => unreachable!("the evaluator rejects truth values as operands"),
+        }
+    }
+
+    pub(crate) fn add(self, r: Value) -> Result<Value, EvalError> {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) => checked(n.checked_add(m)).map(Value::Number),
+            _ => {
+                let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+                // A sum only reaches its bound if both operands reach theirs.
+                let (o, p) = (self.openness(), r.openness());
+                let openness = Openness {
+                    lower: o.lower || p.lower,
+                    upper: o.upper || p.upper,
+                };
+                Ok(Value::Interval(a.add(c)?, b.add(d)?, openness))
+            }
+        }
+    }
+
+    pub(crate) fn neg(self) -> Value {
+        match self {
+            Value::Number(n) => Value::Number(-n),
+            _ => {
+                let (a, b) = self.bounds();
+                let o = self.openness();
+                let openness = Openness {
+                    lower: o.upper,
+                    upper: o.lower,
+                };
+                Value::Interval(b.neg(), a.neg(), openness)
+            }
+        }
+    }
+
+    pub(crate) fn sub(self, r: Value) -> Result<Value, EvalError> {
+        self.add(r.neg())
+    }
+
+    pub(crate) fn mul(self, r: Value) -> Result<Value, EvalError> {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) => checked(n.checked_mul(m)).map(Value::Number),
+            _ => {
+                let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+                // The extremes of a product of intervals always lie on one of its corners.
+                let corners = [a.mul(c)?, a.mul(d)?, b.mul(c)?, b.mul(d)?];
+                // Open ends are closed off, which can only make the interval wider.
+                Ok(Value::Interval(
+                    corners.into_iter().min().unwrap(),
+                    corners.into_iter().max().unwrap(),
+                    Openness::CLOSED,
+                ))
+            }
+        }
+    }
+
+    pub(crate) fn div(self, r: Value) -> Result<Value, EvalError> {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) if n.is_zero() && m.is_zero() => {
+                Err(EvalError::undefined("0 / 0"))
+            }
+            (_, Value::Number(m)) if m.is_zero() => Err(EvalError::DivisionByZero),
+            (_, Value::Interval(c, d, _)) if c <= Bound::ZERO && d >= Bound::ZERO => {
+                Err(EvalError::DivisorContainsZero)
+            }
+            (Value::Number(n), Value::Number(m)) => checked(n.checked_div(m)).map(Value::Number),
+            _ => {
+                let (c, d) = r.bounds();
+                self.mul(Value::Interval(d.recip()?, c.recip()?, Openness::CLOSED))
+            }
+        }
+    }
+
+    pub(crate) fn floor_div(self, r: Value) -> Result<Value, EvalError> {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) if n.is_zero() && m.is_zero() => {
+                Err(EvalError::undefined("0 // 0"))
+            }
+            (Value::Number(_), Value::Number(m)) if m.is_zero() => Err(EvalError::DivisionByZero),
+            (Value::Number(n), Value::Number(m)) => {
+                checked(n.checked_div(m)).map(|q| Value::Number(q.floor()))
+            }
+            _ => Err(EvalError::IntegerDivisionOfInterval),
+        }
+    }
+
+    pub(crate) fn rem(self, r: Value) -> Result<Value, EvalError> {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) if n.is_zero() && m.is_zero() => {
+                Err(EvalError::undefined("0 % 0"))
+            }
+            (Value::Number(_), Value::Number(m)) if m.is_zero() => Err(EvalError::DivisionByZero),
+            (Value::Number(n), Value::Number(m)) => {
+                let q = checked(n.checked_div(m))?.floor();
+                checked(m.checked_mul(q).and_then(|p| n.checked_sub(p))).map(Value::Number)
+            }
+            _ => Err(EvalError::IntegerDivisionOfInterval),
+        }
+    }
+
+    /// The midpoint of this value, which is the value itself for a number.
+    pub(crate) fn mid(self) -> Result<Value, EvalError> {
+        match self.bounds() {
+            (Bound::Finite(a), Bound::Finite(b)) => {
+                checked(a.checked_add(b)).map(|sum| Value::Number(sum / Decimal::TWO))
+            }
+            _ => Err(EvalError::Unbounded),
+        }
+    }
+
+    /// Half the width of this value, which is zero for a number.
+    pub(crate) fn radius(self) -> Result<Value, EvalError> {
+        match self.bounds() {
+            (Bound::Finite(a), Bound::Finite(b)) => {
+                checked(b.checked_sub(a)).map(|width| Value::Number(width / Decimal::TWO))
+            }
+            _ => Err(EvalError::Unbounded),
+        }
+    }
+
+    pub(crate) fn abs(self) -> Value {
+        match self {
+            Value::Number(n) => Value::Number(n.abs()),
+            // Zero itself is reached when the interval straddles it.
+            Value::Interval(a, b, _) if a < Bound::ZERO && b > Bound::ZERO => {
+                Value::Interval(Bound::ZERO, a.abs().max(b.abs()), Openness::CLOSED)
+            }
+            _ => {
+                let (a, b) = self.bounds();
+                Value::interval(a.abs(), b.abs())
+            }
+        }
+    }
+
+    pub(crate) fn min(self, r: Value) -> Value {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) => Value::Number(n.min(m)),
+            _ => {
+                let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+                Value::Interval(a.min(c), b.min(d), Openness::CLOSED)
+            }
+        }
+    }
+
+    pub(crate) fn max(self, r: Value) -> Value {
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) => Value::Number(n.max(m)),
+            _ => {
+                let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+                Value::Interval(a.max(c), b.max(d), Openness::CLOSED)
+            }
+        }
+    }
+
+    pub(crate) fn sqrt(self) -> Result<Value, EvalError> {
+        match self {
+            Value::Number(n) if n < Decimal::ZERO => Err(EvalError::NegativeSqrt),
+            Value::Interval(a, ..) if a < Bound::ZERO => Err(EvalError::NegativeSqrt),
+            Value::Number(n) => Ok(Value::Number(n.sqrt().unwrap_or_default())),
+            _ => {
+                let (a, b) = self.bounds();
+                Ok(Value::Interval(a.sqrt(), b.sqrt(), Openness::CLOSED))
+            }
+        }
+    }
+
+    pub(crate) fn union(self, r: Value) -> Value {
+        let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+        Value::Interval(a.min(c), b.max(d), Openness::CLOSED)
+    }
+
+    pub(crate) fn intersect(self, r: Value) -> Result<Value, EvalError> {
+        let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+        let (o, p) = (self.openness(), r.openness());
+        // Each bound of the intersection is the tighter of the two, left out if either operand
+        // leaves it out.
+        let (lower, lower_open) = match a.cmp(&c) {
+            Ordering::Less => (c, p.lower),
+            Ordering::Greater => (a, o.lower),
+            Ordering::Equal => (a, o.lower || p.lower),
+        };
+        let (upper, upper_open) = match b.cmp(&d) {
+            Ordering::Less => (b, o.upper),
+            Ordering::Greater => (d, p.upper),
+            Ordering::Equal => (b, o.upper || p.upper),
+        };
+        if lower > upper || (lower == upper && (lower_open || upper_open)) {
+            return Err(EvalError::EmptyIntersection);
+        }
+        let openness = Openness {
+            lower: lower_open,
+            upper: upper_open,
+        };
+        Ok(Value::Interval(lower, upper, openness))
+    }
+
+    /// `1` if `r` lies entirely within this value, `0` otherwise.
+    pub(crate) fn contains(self, r: Value) -> Value {
+        Value::Number(if self.encloses(r) {
+            Decimal::ONE
+        } else {
+            Decimal::ZERO
+        })
+    }
+
+    /// Whether `r` lies entirely within this value.
+    pub(crate) fn encloses(self, r: Value) -> bool {
+        let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+        let (o, p) = (self.openness(), r.openness());
+        // A shared bound is only a problem when this value leaves it out and `r` doesn't.
+        let lower = a < c || (a == c && (!o.lower || p.lower));
+        let upper = d < b || (d == b && (!o.upper || p.upper));
+        lower && upper
+    }
+
+    /// Whether this value equals `r` up to `epsilon`, comparing intervals endpoint by endpoint.
+    pub(crate) fn within(self, r: Value, epsilon: Decimal) -> bool {
+        let close = |a: Bound, b: Bound| match (a, b) {
+            (Bound::Finite(a), Bound::Finite(b)) => {
+                a.checked_sub(b).is_some_and(|d| d.abs() <= epsilon)
+            }
+            (a, b) => a == b,
+        };
+        match (self, r) {
+            (Value::Number(n), Value::Number(m)) => close(n.into(), m.into()),
+            (Value::Interval(a, b, o), Value::Interval(c, d, p)) => {
+                o == p && close(a, c) && close(b, d)
+            }
+            _ => self == r,
+        }
+    }
+
+    /// Whether this value is certainly less than `r`, certainly not, or either depending on where
+    /// in their intervals the actual values lie.
+    pub(crate) fn less(self, r: Value) -> Value {
+        let ((a, b), (c, d)) = (self.bounds(), r.bounds());
+        // Touching intervals are still ordered when either leaves out the shared bound.
+        let touching = b == c && (self.openness().upper || r.openness().lower);
+        Value::Truth(if b < c || touching {
+            Truth::True
+        } else if a >= d {
+            Truth::False
+        } else {
+            Truth::Indeterminate
+        })
+    }
+
+    fn round_bounds(self, dp: u32, lower: RoundingStrategy, upper: RoundingStrategy) -> Value {
+        let (a, b) = self.bounds();
+        Value::Interval(a.round(dp, lower), b.round(dp, upper), Openness::CLOSED)
+    }
+
+    pub(crate) fn round(self, dp: u32) -> Value {
+        match self {
+            Value::Number(n) => {
+                Value::Number(n.round_dp_with_strategy(dp, RoundingStrategy::MidpointAwayFromZero))
+            }
+            // Rounding outwards keeps every value the interval stands for within it.
+            _ => self.round_bounds(
+                dp,
+                RoundingStrategy::ToNegativeInfinity,
+                RoundingStrategy::ToPositiveInfinity,
+            ),
+        }
+    }
+
+    pub(crate) fn floor(self, dp: u32) -> Value {
+        let down = RoundingStrategy::ToNegativeInfinity;
+        match self {
+            Value::Number(n) => Value::Number(n.round_dp_with_strategy(dp, down)),
+            _ => self.round_bounds(dp, down, down),
+        }
+    }
+
+    pub(crate) fn ceil(self, dp: u32) -> Value {
+        let up = RoundingStrategy::ToPositiveInfinity;
+        match self {
+            Value::Number(n) => Value::Number(n.round_dp_with_strategy(dp, up)),
+            _ => self.round_bounds(dp, up, up),
+        }
+    }
+
+    pub(crate) fn recip(self) -> Result<Value, EvalError> {
+        Value::Number(Decimal::ONE).div(self)
+    }
+
+    pub(crate) fn pow(self, r: Value) -> Result<Value, EvalError> {
+        let exp = match r {
+            Value::Number(n) if n.fract().is_zero() => n.to_i64(),
+            _ => None,
+        }
+        .ok_or(EvalError::NonIntegerExponent)?;
+
+        if exp < 0 {
+            // `i64::MIN` has no positive counterpart.
+            let exp = exp.checked_neg().ok_or(EvalError::Overflow)?;
+            return Value::Number(Decimal::ONE).div(self.pow(Value::Number(exp.into()))?);
+        }
+
+        Ok(match self {
+            Value::Number(n) => Value::Number(checked(n.checked_powi(exp))?),
+            _ => {
+                let (a, b) = self.bounds();
+                let (pa, pb) = (a.powi(exp)?, b.powi(exp)?);
+                // Even powers fold the negative half onto the positive one, so an interval
+                // straddling zero bottoms out at zero rather than at either endpoint.
+                if exp % 2 == 0 && a < Bound::ZERO && b > Bound::ZERO {
+                    Value::Interval(Bound::ZERO, pa.max(pb), Openness::CLOSED)
+                } else {
+                    Value::interval(pa, pb)
+                }
+            }
+        })
+    }
+}
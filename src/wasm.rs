@@ -0,0 +1,32 @@
+//! An entry point for embedding `calc`, e.g. in a browser through WebAssembly, where errors can't
+//! be printed to stderr.
+
+use ariadne::{Config, Label, Report, ReportKind, Source};
+use chumsky::span::SimpleSpan;
+
+use crate::{evaluate, parse_document_with, pretty_print, FormatOptions};
+
+/// Parses, evaluates and formats `src`, returning either the formatted document or every error
+/// rendered the way the command line reports them, without colors.
+pub fn run(src: &str, opts: &FormatOptions) -> Result<String, String> {
+    let mut lines = parse_document_with(src, opts.locale).map_err(|errs| {
+        errs.iter()
+            .map(|e| render(src, *e.span(), e.to_string(), e.reason().to_string()))
+            .collect::<String>()
+    })?;
+    evaluate(&mut lines)
+        .map_err(|(e, span)| render(src, span, e.to_string(), "in this expression"))?;
+    pretty_print(lines, opts).map_err(|_| "cannot format the document".to_string())
+}
+
+fn render(src: &str, span: SimpleSpan, message: impl ToString, label: impl ToString) -> String {
+    let mut out = Vec::new();
+    let report = Report::build(ReportKind::Error, (), span.start)
+        .with_config(Config::default().with_color(false))
+        .with_message(message)
+        .with_label(Label::new(span.into_range()).with_message(label))
+        .finish();
+    // Writing to a `Vec` can't fail.
+    let _ = report.write(Source::from(src), &mut out);
+    String::from_utf8_lossy(&out).into_owned()
+}
@@ -0,0 +1,413 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+    thread,
+    time::Duration,
+};
+
+// Runs the binary with `stdin` as its standard input.
+fn calc(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calc"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The binary may exit before reading its input, e.g. on bad flags.
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+// The subtotal printed under a document made of `expression` alone.
+fn total(expression: &str) -> String {
+    let out = stdout(&calc(&[], &format!("{expression}\n---\n\n")));
+    let mut lines = out
+        .lines()
+        .skip_while(|line| line.is_empty() || line.chars().any(|c| c != '-'));
+    lines.nth(1).unwrap_or_default().trim().to_string()
+}
+
+// A document made of `expression` alone, as printed back.
+fn printed(expression: &str) -> String {
+    stdout(&calc(&[], &format!("{expression}\n")))
+        .trim()
+        .to_string()
+}
+
+// The errors reported for a document made of `expression` alone.
+fn errors(expression: &str) -> String {
+    stderr(&calc(&[], &format!("{expression}\n---\n\n")))
+}
+
+// The words of a rendered document, leaving out rules, whose width depends on the values around.
+fn words(out: &str) -> Vec<&str> {
+    out.lines()
+        .filter(|line| !line.chars().all(|c| c == '-'))
+        .flat_map(str::split_whitespace)
+        .collect()
+}
+
+#[test]
+fn multiplication_binds_tighter_than_addition() {
+    assert_eq!(total("2 + 3 * 4"), "14");
+    assert_eq!(total("10 - 2 * 3"), "4");
+    assert_eq!(total("2 * 3 + 4 * 5 - 1"), "25");
+    assert_eq!(total("10 - 3 - 2"), "5");
+    assert_eq!(total("[1, 2] + 3 * 2"), "[7, 8]");
+}
+
+#[test]
+fn parentheses_group_operations() {
+    assert_eq!(total("(2 + 3) * 4"), "20");
+    assert_eq!(total("10 - (3 - 2)"), "9");
+    assert_eq!(total("((1 + 1) * (2 + 2))"), "8");
+    assert_eq!(total("([1, 2] + 1) * 2"), "[4, 6]");
+}
+
+#[test]
+fn interval_products_take_the_extreme_corners() {
+    assert_eq!(total("[-1, 2] * [-3, 4]"), "[-6, 8]");
+    assert_eq!(total("[-2, -1] * [3, 4]"), "[-8, -3]");
+    assert_eq!(total("[-2, -1] * [-4, -3]"), "[3, 8]");
+    assert_eq!(total("[1, 2] * [3, 4]"), "[3, 8]");
+    assert_eq!(total("[2, 2] * [3, 3]"), "[6, 6]");
+}
+
+#[test]
+fn intervals_divide_unless_the_divisor_contains_zero() {
+    assert_eq!(total("[2, 4] / [1, 2]"), "[1, 4]");
+    for divisor in ["[-1, 1]", "[0, 1]"] {
+        let errors = errors(&format!("[2, 4] / {divisor}"));
+        assert!(
+            errors.contains("divisor interval contains zero"),
+            "{errors}"
+        );
+    }
+}
+
+#[test]
+fn division_by_zero_is_reported() {
+    for expression in ["6 / 0", "[1, 2] / 0"] {
+        let errors = errors(expression);
+        assert!(errors.contains("division by zero"), "{errors}");
+    }
+}
+
+#[test]
+fn negative_factors_flip_intervals() {
+    assert_eq!(total("[1, 2] * -3"), "[-6, -3]");
+    assert_eq!(total("-3 * [1, 2]"), "[-6, -3]");
+    assert_eq!(total("[-1, 2] * -2"), "[-4, 2]");
+}
+
+#[test]
+fn negative_divisors_flip_intervals() {
+    assert_eq!(total("[2, 4] / -2"), "[-2, -1]");
+    assert_eq!(total("[-4, 2] / -2"), "[-1, 2]");
+    assert_eq!(total("[2, 4] / [-2, -1]"), "[-4, -1]");
+}
+
+#[test]
+fn reversed_bounds_are_put_in_order() {
+    assert_eq!(total("[5, 1]"), "[1, 5]");
+    assert_eq!(total("[-1, -3]"), "[-3, -1]");
+}
+
+#[test]
+fn negation_flips_values() {
+    assert_eq!(total("-[1, 3]"), "[-3, -1]");
+    assert_eq!(total("-(2 + 3)"), "-5");
+    assert_eq!(total("2 - -3"), "5");
+}
+
+#[test]
+fn negations_print_back() {
+    assert_eq!(printed("-[1, 3]"), "-[1, 3]");
+    assert_eq!(printed("-(1 + 2)"), "-(1 + 2)");
+}
+
+#[test]
+fn even_powers_of_intervals_straddling_zero_start_at_zero() {
+    assert_eq!(total("[-2, 3] ^ 2"), "[0, 9]");
+    assert_eq!(total("[-3, 2] ^ 2"), "[0, 9]");
+    assert_eq!(total("[-2, 3] ^ 3"), "[-8, 27]");
+    assert_eq!(total("[-3, -2] ^ 2"), "[4, 9]");
+}
+
+#[test]
+fn exponents_are_whole_numbers() {
+    assert_eq!(total("2 ^ -1"), "0.5");
+    let errors = errors("2 ^ 0.5");
+    assert!(errors.contains("exponent must be an integer"), "{errors}");
+}
+
+#[test]
+fn documents_are_read_from_stdin() {
+    for args in [&[] as &[&str], &["-"]] {
+        let output = calc(args, "10\n4\n---\n\n");
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert_eq!(words(&stdout(&output)), ["10", "4", "6"]);
+    }
+}
+
+#[test]
+fn wrong_subtotals_fail_the_check() {
+    let output = calc(&["--check"], "10\n4\n---\n6 right\n20\n---\n7 wrong\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr(&output).matches("subtotal should be").count(), 1);
+    assert!(calc(&["--check"], "10\n4\n---\n6\n").status.success());
+}
+
+#[test]
+fn malformed_documents_fail() {
+    let output = calc(&[], "[1, 2\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!stderr(&output).is_empty());
+    assert_eq!(calc(&[], "6 / 0\n").status.code(), Some(1));
+    assert!(calc(&[], "1\n").status.success());
+}
+
+// A path in the temporary directory, unique to this test run and `name`.
+fn temp_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("calc-{}-{name}", std::process::id()));
+    path.to_str().unwrap().to_string()
+}
+
+// A file in the temporary directory holding `contents`.
+fn temp_file(name: &str, contents: &str) -> String {
+    let path = temp_path(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn output_files_get_what_stdout_would() {
+    let src = "10 rent\n4\n---\n\n";
+    let path = temp_path("output");
+    let output = calc(&["--output", &path], src);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(output.stdout.is_empty());
+    let written = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(written, stdout(&calc(&[], src)));
+}
+
+#[test]
+fn each_file_is_run_even_when_another_fails() {
+    let good = temp_file("good.calc", "10\n4\n---\n\n");
+    let bad = temp_file("bad.calc", "[1, 2\n");
+    let output = calc(&[&good, &bad], "");
+    std::fs::remove_file(&good).unwrap();
+    std::fs::remove_file(&bad).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let out = stdout(&output);
+    assert!(out.starts_with(&format!("==> {good} <==\n")), "{out}");
+    assert!(out.contains(&format!("==> {bad} <==\n")), "{out}");
+    assert!(out.lines().any(|line| line.trim() == "6"), "{out}");
+    assert!(stderr(&output).contains(&bad), "{}", stderr(&output));
+}
+
+#[test]
+fn explanations_go_to_stderr() {
+    let output = calc(&["--explain"], "10\n3\n2\n");
+    assert!(output.status.success());
+    assert_eq!(stderr(&output), "start 10 → - 3 = 7 → - 2 = 5\n");
+}
+
+#[test]
+fn rule_chars_other_than_total_rules_are_rejected() {
+    for c in ["~", "|", "*"] {
+        let output = calc(&["--rule-char", c], "1\n");
+        assert_eq!(output.status.code(), Some(2));
+        assert!(stderr(&output).contains("--rule-char"));
+    }
+    let output = calc(&["--rule-char", "="], "1\n2\n---\n\n");
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("\n==\n"));
+}
+
+#[test]
+fn watched_files_are_run_again_when_they_change() {
+    let input = temp_file("watched.calc", "1\n2\n---\n\n");
+    let output = temp_path("watched.out");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_calc"))
+        .args(["--watch", input.as_str(), "--output", output.as_str()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    // Waits a few seconds at most for the output to show `text`.
+    let shows = |text: &str| {
+        (0..50).any(|_| {
+            thread::sleep(Duration::from_millis(100));
+            std::fs::read_to_string(&output).is_ok_and(|out| out.contains(text))
+        })
+    };
+
+    let first = shows("\n-1 ");
+    std::fs::write(&input, "5\n2\n---\n\n").unwrap();
+    let second = shows("\n3 ");
+    child.kill().unwrap();
+    child.wait().unwrap();
+    std::fs::remove_file(&input).unwrap();
+    std::fs::remove_file(&output).unwrap();
+    assert!(first && second);
+}
+
+#[test]
+fn errors_on_stdin_are_reported_under_its_name() {
+    for args in [&[] as &[&str], &["-"]] {
+        let output = calc(args, "[1, 2\n");
+        assert!(stderr(&output).contains("<stdin>"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn parse_only_dumps_the_document() {
+    let output = calc(&["--parse-only"], "a = 2\nb = 3\na * b\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Mul("), "{}", stdout(&output));
+    assert_eq!(calc(&["--parse-only"], "[1, 2\n").status.code(), Some(1));
+}
+
+#[test]
+fn quiet_runs_print_one_line() {
+    let output = calc(&["--quiet"], "10\n4\n---\n\n20\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "6\n");
+    let output = calc(&["-q"], "10\n4\n");
+    assert_eq!(stdout(&output), "6\n");
+}
+
+#[test]
+fn color_is_only_used_when_asked_for() {
+    let never = calc(&["--color", "never"], "[1, 2\n");
+    assert!(!stderr(&never).contains('\x1b'), "{}", stderr(&never));
+    let always = calc(&["--color", "always"], "[1, 2\n");
+    assert!(stderr(&always).contains('\x1b'), "{}", stderr(&always));
+}
+
+#[test]
+fn final_totals_end_documents_without_subtotals() {
+    let output = calc(&["--final"], "10\n3\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(words(&stdout(&output)), ["10", "3", "7"]);
+    let output = calc(&["--final"], "10\n3\n---\n\n");
+    assert_eq!(words(&stdout(&output)), ["10", "3", "7"]);
+}
+
+#[test]
+fn summaries_count_and_add_up_the_operations() {
+    let output = calc(&["--summary"], "1\n2\n3\n4\n5\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).ends_with("\ncount: 5\nsum: 15\n"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn streamed_errors_point_at_their_line_in_the_file() {
+    let output = calc(&["--stream"], "1\n2\n---\n\n5\n6 / 0\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains(":6:"), "{}", stderr(&output));
+}
+
+#[test]
+fn streaming_matches_batch_output() {
+    let src = "10\n4\n---\n\n20\n5\ntotal: 15 food\n[1, 2]\n";
+    let streamed = calc(&["--stream"], src);
+    assert!(streamed.status.success(), "{}", stderr(&streamed));
+    let batch = calc(&[], src);
+    assert_eq!(words(&stdout(&streamed)), words(&stdout(&batch)));
+}
+
+#[test]
+fn errors_past_the_limit_are_counted() {
+    let src = "1\n---\n9\n".repeat(5);
+    let output = calc(&["--check", "--max-errors", "2"], &src);
+    assert_eq!(output.status.code(), Some(1));
+    let err = stderr(&output);
+    assert_eq!(err.matches("subtotal should be").count(), 2, "{err}");
+    assert!(err.ends_with("(… and 3 more errors)\n"), "{err}");
+}
+
+#[test]
+fn streaming_keeps_continued_lines_together() {
+    let src = "10\n- 5\n  * 2\n  * 3\n---\n\n[1, 2]\n| [3, 4]\n  & [2, 5]\n  | [7, 8]\n---\n4\n";
+    let streamed = calc(&["--stream"], src);
+    assert!(streamed.status.success(), "{}", stderr(&streamed));
+    let batch = calc(&[], src);
+    assert_eq!(words(&stdout(&streamed)), words(&stdout(&batch)));
+}
+
+#[test]
+fn sliced_subtotals_include_earlier_lines() {
+    let output = calc(&["--from-line", "3"], "10\n4\n---\n\n");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(words(&stdout(&output)), ["6"]);
+}
+
+#[test]
+fn sliced_operations_keep_how_they_entered_the_total() {
+    let output = calc(&["--show-op", "--from-line", "2"], "10\n4\n");
+    assert!(stdout(&output).starts_with("- 4"), "{}", stdout(&output));
+}
+
+#[test]
+fn slices_count_lines_after_an_empty_result_line() {
+    let src = "10\n4\n---\n\n# note\n2\n";
+    let output = calc(&["--from-line", "5"], src);
+    assert!(
+        stdout(&output).starts_with("# note\n"),
+        "{}",
+        stdout(&output)
+    );
+    let output = calc(&["--to-line", "4"], src);
+    assert!(!stdout(&output).contains("# note"), "{}", stdout(&output));
+}
+
+#[test]
+fn streamed_sections_keep_earlier_variables() {
+    let src = "rate = 2\n10\n---\n\n5 * rate\n--- five:\n\nfive / 2\n";
+    let streamed = calc(&["--stream"], src);
+    assert!(streamed.status.success(), "{}", stderr(&streamed));
+    let batch = calc(&[], src);
+    assert_eq!(words(&stdout(&streamed)), words(&stdout(&batch)));
+}
+
+#[test]
+fn undefined_results_label_their_operands() {
+    for expression in ["0 / 0", "[inf, inf] - [inf, inf]"] {
+        let errors = errors(expression);
+        assert!(errors.contains("undefined result"), "{errors}");
+        assert!(errors.contains("this operand"), "{errors}");
+        assert!(errors.contains("and this one"), "{errors}");
+    }
+}
+
+#[test]
+fn precisions_go_up_to_what_a_decimal_holds() {
+    // The total of these loses a digit to rounding, leaving something to reconcile.
+    let src = "10 / 3\n1 / 3\n---\n\n";
+    let output = calc(&["--sum", "--reconcile", "--precision", "28"], src);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = calc(&["--sum", "--reconcile", "--precision", "29"], src);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("--precision"),
+        "{}",
+        stderr(&output)
+    );
+}
@@ -0,0 +1,598 @@
+use calc::{
+    check, check_within, evaluate, evaluate_iter, evaluate_traced, evaluate_with, evaluated,
+    parse_document, Bound, EvalError, EvalOptions, Line, LineKind, Openness, Operation,
+    SubtotalKind, Truth, Value,
+};
+use chumsky::span::SimpleSpan;
+use rust_decimal::Decimal;
+
+fn dec(s: &str) -> Decimal {
+    s.parse().unwrap()
+}
+
+fn num(s: &str) -> Value {
+    Value::Number(dec(s))
+}
+
+// The bounds of the closed interval from `a` to `b`.
+fn bounds(a: &str, b: &str) -> (Bound, Bound) {
+    (Bound::Finite(dec(a)), Bound::Finite(dec(b)))
+}
+
+fn interval(a: &str, b: &str, lower: bool, upper: bool) -> Value {
+    let (a, b) = bounds(a, b);
+    Value::Interval(a, b, Openness { lower, upper })
+}
+
+fn closed(a: &str, b: &str) -> Value {
+    interval(a, b, false, false)
+}
+
+// The value of the first line of `src`.
+fn eval(src: &str) -> Result<Value, EvalError> {
+    let mut lines = parse_document(src).unwrap();
+    evaluate(&mut lines).map_err(|(e, _)| e)?;
+    match &lines[0] {
+        Line::Operation { value, .. } | Line::Assign { value, .. } => Ok(value.unwrap()),
+        line => panic!("{line:?}"),
+    }
+}
+
+// The values of the subtotals of `src`, evaluated with `opts`.
+fn subtotals_with(src: &str, opts: &EvalOptions) -> Vec<Value> {
+    let mut lines = parse_document(src).unwrap();
+    evaluate_with(&mut lines, opts).unwrap();
+    lines
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Subtotal { value, .. } => value,
+            _ => None,
+        })
+        .collect()
+}
+
+fn subtotals(src: &str) -> Vec<Value> {
+    subtotals_with(src, &EvalOptions::default())
+}
+
+#[test]
+fn documents_evaluate_through_the_library() {
+    let mut lines = parse_document("10\n4\n---\n\n").unwrap();
+    evaluate(&mut lines).unwrap();
+    assert!(
+        matches!(
+            &lines[2],
+            Line::Subtotal { value: Some(Value::Number(n)), .. } if *n == dec("6")
+        ),
+        "{lines:?}"
+    );
+}
+
+#[test]
+fn comments_are_left_out_of_totals() {
+    let src = "# rent\n1000\n  # food, weekly\n250\n---\n\n";
+    assert!(matches!(subtotals(src)[..], [Value::Number(n)] if n == dec("750")));
+    let lines = parse_document(src).unwrap();
+    assert!(matches!(&lines[0], Line::Comment(c, _) if c == "# rent"));
+    assert!(matches!(&lines[2], Line::Comment(c, _) if c == "  # food, weekly"));
+}
+
+#[test]
+fn sections_start_their_totals_afresh() {
+    let src = "10\n4\n---\n\n20\n5\n---\n\n";
+    let sections = EvalOptions {
+        sections: true,
+        ..EvalOptions::default()
+    };
+    assert!(matches!(
+        subtotals_with(src, &sections)[..],
+        [Value::Number(a), Value::Number(b)] if a == dec("6") && b == dec("15")
+    ));
+    assert!(matches!(
+        subtotals(src)[..],
+        [Value::Number(a), Value::Number(b)] if a == dec("6") && b == dec("-19")
+    ));
+}
+
+#[test]
+fn sums_add_what_totals_subtract() {
+    let src = "10\n4\n[1, 2]\n---\n\n";
+    let sum = EvalOptions {
+        sum: true,
+        ..EvalOptions::default()
+    };
+    assert!(matches!(
+        subtotals_with(src, &sum)[..],
+        [Value::Interval(a, b, _)] if (a, b) == bounds("15", "16")
+    ));
+    assert!(matches!(
+        subtotals(src)[..],
+        [Value::Interval(a, b, _)] if (a, b) == bounds("4", "5")
+    ));
+}
+
+#[test]
+fn grand_totals_add_up_the_sections() {
+    let mut lines = parse_document("10\n4\n---\n\n20\n5\n---\n\n[1, 2]\n---\n\n").unwrap();
+    let sections = EvalOptions {
+        sections: true,
+        ..EvalOptions::default()
+    };
+    let total = evaluate_with(&mut lines, &sections).unwrap();
+    assert!(matches!(
+        total,
+        Some(Value::Interval(a, b, _)) if (a, b) == bounds("22", "23")
+    ));
+}
+
+#[test]
+fn percentages_are_hundredths() {
+    for (src, n) in [
+        ("5%", "0.05"),
+        ("12.5%", "0.125"),
+        ("-10%", "-0.1"),
+        ("200 * 5%", "10"),
+    ] {
+        assert!(
+            matches!(eval(src), Ok(Value::Number(v)) if v == dec(n)),
+            "{src}"
+        );
+    }
+}
+
+#[test]
+fn unbounded_intervals_stay_unbounded() {
+    assert!(matches!(
+        eval("[5, inf] - 3"),
+        Ok(Value::Interval(Bound::Finite(a), Bound::PosInf, _)) if a == dec("2")
+    ));
+    assert!(matches!(
+        eval("[inf, inf] - [inf, inf]"),
+        Err(EvalError::Undefined {
+            form: "inf - inf",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn uncertainties_make_intervals() {
+    for (src, a, b) in [
+        ("-3 ± 1", "-4", "-2"),
+        ("5 ± 0.2", "4.8", "5.2"),
+        ("5 +- 0.2", "4.8", "5.2"),
+    ] {
+        assert!(
+            matches!(eval(src), Ok(Value::Interval(l, u, _)) if (l, u) == bounds(a, b)),
+            "{src}"
+        );
+    }
+}
+
+#[test]
+fn check_reports_only_wrong_subtotals() {
+    let mut lines = parse_document("10\n4\n---\n6 right\n20\n---\n7 wrong\n").unwrap();
+    evaluate(&mut lines).unwrap();
+    let errors = check(&lines);
+    assert_eq!(errors.len(), 1, "{errors:?}");
+    assert!(matches!(
+        errors[0].0,
+        EvalError::SubtotalMismatch { written, computed }
+            if written == num("7") && computed == num("-14")
+    ));
+}
+
+#[test]
+fn midpoints_and_radii_describe_intervals() {
+    assert_eq!(eval("mid([1, 3])").unwrap(), num("2"));
+    assert_eq!(eval("radius([1, 3])").unwrap(), num("1"));
+    assert_eq!(eval("mid(5)").unwrap(), num("5"));
+    assert_eq!(eval("radius(5)").unwrap(), num("0"));
+    assert!(matches!(eval("mid([1, inf])"), Err(EvalError::Unbounded)));
+}
+
+#[test]
+fn absolute_values_of_intervals_straddling_zero_start_at_zero() {
+    assert_eq!(eval("abs(-4)").unwrap(), num("4"));
+    assert_eq!(eval("abs([-3, 2])").unwrap(), closed("0", "3"));
+    assert_eq!(eval("abs([2, 5])").unwrap(), closed("2", "5"));
+    assert_eq!(eval("abs([-5, -2])").unwrap(), closed("2", "5"));
+}
+
+#[test]
+fn minimums_and_maximums_work_bound_by_bound() {
+    assert_eq!(eval("min(3, 5)").unwrap(), num("3"));
+    assert_eq!(eval("max(3, 5)").unwrap(), num("5"));
+    assert_eq!(eval("min([1, 5], [2, 3])").unwrap(), closed("1", "3"));
+    assert_eq!(eval("max([1, 5], [2, 3])").unwrap(), closed("2", "5"));
+    assert_eq!(eval("max([1, 5], 4)").unwrap(), closed("4", "5"));
+}
+
+// Whether the bounds of `v` are within 1e-20 of those of `expected`, for inexact results.
+fn approx(v: Value, expected: Value) -> bool {
+    let bounds = |v: Value| match v {
+        Value::Number(n) => (n, n),
+        Value::Interval(Bound::Finite(a), Bound::Finite(b), _) => (a, b),
+        v => panic!("{v:?}"),
+    };
+    let ((a, b), (c, d)) = (bounds(v), bounds(expected));
+    let epsilon = Decimal::new(1, 20);
+    (a - c).abs() < epsilon && (b - d).abs() < epsilon
+}
+
+#[test]
+fn square_roots_reject_negative_values() {
+    assert!(approx(eval("sqrt(9)").unwrap(), num("3")));
+    assert!(approx(
+        eval("sqrt(2)").unwrap(),
+        num("1.4142135623730950488016887242")
+    ));
+    assert!(approx(eval("sqrt([4, 9])").unwrap(), closed("2", "3")));
+    assert!(matches!(eval("sqrt(-1)"), Err(EvalError::NegativeSqrt)));
+    assert!(matches!(
+        eval("sqrt([-1, 4])"),
+        Err(EvalError::NegativeSqrt)
+    ));
+}
+
+#[test]
+fn unions_and_intersections_of_intervals() {
+    assert_eq!(eval("[1, 3] | [2, 5]").unwrap(), closed("1", "5"));
+    assert_eq!(eval("[1, 3] & [2, 5]").unwrap(), closed("2", "3"));
+    assert_eq!(eval("[1, 2] | [4, 5]").unwrap(), closed("1", "5"));
+    assert!(matches!(
+        eval("[1, 2] & [4, 5]"),
+        Err(EvalError::EmptyIntersection)
+    ));
+}
+
+#[test]
+fn containment_is_one_or_zero() {
+    assert_eq!(eval("contains([1, 5], 3)").unwrap(), num("1"));
+    assert_eq!(eval("contains([1, 5], 9)").unwrap(), num("0"));
+    assert_eq!(eval("contains([1, 5], [2, 6])").unwrap(), num("0"));
+}
+
+// The value of every operation and definition of `src`, in order.
+fn values(src: &str) -> Vec<Value> {
+    let mut lines = parse_document(src).unwrap();
+    evaluate(&mut lines).unwrap();
+    lines
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Operation { value, .. } | Line::Assign { value, .. } => value,
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn variables_stand_for_their_values() {
+    assert_eq!(
+        values("rate = 7%\n100 * rate\nrate * 2\n"),
+        [num("0.07"), num("7"), num("0.14")]
+    );
+    assert!(parse_document("x * 2\n").is_err());
+}
+
+#[test]
+fn undefined_names_start_the_comment() {
+    let lines = evaluated(parse_document("100 - groceries\n").unwrap()).unwrap();
+    match &lines[..] {
+        [Line::Operation {
+            value: Some(v),
+            comment,
+            ..
+        }] => {
+            assert_eq!(*v, num("100"));
+            assert_eq!(comment, "- groceries");
+        }
+        lines => panic!("{lines:?}"),
+    }
+    assert_eq!(
+        values("1200 / month\nmonth = 12\n1200 / month\n"),
+        [num("1200"), num("12"), num("100")]
+    );
+}
+
+#[test]
+fn at_signs_stand_for_the_last_subtotal() {
+    assert_eq!(values("50\n---\n\n@ * 2\n"), [num("50"), num("100")]);
+    assert!(matches!(eval("@ * 2"), Err(EvalError::NoSubtotal)));
+}
+
+#[test]
+fn deeply_nested_operations_evaluate_and_drop() {
+    let span = SimpleSpan::from(0..0);
+    let one = || Box::new(Operation::Value(num("1"), span));
+    let mut operation = Operation::Value(num("1"), span);
+    for _ in 0..100_000 {
+        operation = Operation::Mul(Box::new(operation), one(), span);
+    }
+    let mut lines = vec![Line::Operation {
+        operation,
+        value: None,
+        running: None,
+        step: None,
+        comment: "".into(),
+        span,
+    }];
+
+    evaluate(&mut lines).unwrap();
+    let Line::Operation { value, .. } = &lines[0] else {
+        unreachable!()
+    };
+    assert_eq!(*value, Some(num("1")));
+}
+
+#[test]
+fn traces_follow_the_running_total() {
+    let mut lines = parse_document("10\n3\n2\n").unwrap();
+    let (total, trace) = evaluate_traced(&mut lines, &EvalOptions::default()).unwrap();
+    assert_eq!(total, Some(num("5")));
+    let steps: Vec<String> = trace.iter().map(ToString::to_string).collect();
+    assert_eq!(steps, ["start 10", "- 3 = 7", "- 2 = 5"]);
+}
+
+#[test]
+fn reciprocals_reject_zero() {
+    assert_eq!(eval("recip(4)").unwrap(), num("0.25"));
+    assert_eq!(eval("recip([2, 4])").unwrap(), closed("0.25", "0.5"));
+    assert!(matches!(
+        eval("recip([-1, 1])"),
+        Err(EvalError::DivisorContainsZero)
+    ));
+    assert!(matches!(eval("recip(0)"), Err(EvalError::DivisionByZero)));
+}
+
+#[test]
+fn evaluating_by_value_agrees_with_evaluating_in_place() {
+    let src = "10\n4\n---\n\nx = @ * 2\nx\n[1, 2]\n---\n\n";
+    let mut lines = parse_document(src).unwrap();
+    evaluate(&mut lines).unwrap();
+    assert_eq!(evaluated(parse_document(src).unwrap()).unwrap(), lines);
+    assert!(evaluated(parse_document("6 / 0\n").unwrap()).is_err());
+}
+
+#[test]
+fn comparisons_are_certain_only_without_overlap() {
+    let truth = |src| eval(src).unwrap();
+    assert_eq!(truth("[1, 2] < [3, 4]"), Value::Truth(Truth::True));
+    assert_eq!(truth("[3, 4] < [1, 2]"), Value::Truth(Truth::False));
+    assert_eq!(truth("[3, 4] > [1, 2]"), Value::Truth(Truth::True));
+    assert_eq!(truth("[1, 4] < [2, 3]"), Value::Truth(Truth::Indeterminate));
+    assert_eq!(truth("[1, 3] > [2, 4]"), Value::Truth(Truth::Indeterminate));
+    assert!(matches!(eval("(1 < 2) + 1"), Err(EvalError::NotANumber)));
+}
+
+#[test]
+fn floor_division_and_remainders_round_down() {
+    assert_eq!(eval("7 // 2").unwrap(), num("3"));
+    assert_eq!(eval("-7 // 2").unwrap(), num("-4"));
+    assert_eq!(eval("7 % 2").unwrap(), num("1"));
+    assert_eq!(eval("-7 % 2").unwrap(), num("1"));
+    assert_eq!(eval("7 % -2").unwrap(), num("-1"));
+    assert_eq!(eval("7 % 2 * 3").unwrap(), num("3"));
+    assert!(matches!(
+        eval("[1, 2] // 2"),
+        Err(EvalError::IntegerDivisionOfInterval)
+    ));
+    assert!(matches!(
+        eval("5 % [1, 2]"),
+        Err(EvalError::IntegerDivisionOfInterval)
+    ));
+}
+
+#[test]
+fn intersections_respect_open_bounds() {
+    assert!(matches!(
+        eval("[1, 2) & [2, 3]"),
+        Err(EvalError::EmptyIntersection)
+    ));
+    assert!(matches!(
+        eval("[1, 2] & (2, 3]"),
+        Err(EvalError::EmptyIntersection)
+    ));
+    assert_eq!(eval("[1, 2] & [2, 3]").unwrap(), closed("2", "2"));
+    assert_eq!(
+        eval("[1, 3) & (2, 5]").unwrap(),
+        interval("2", "3", true, true)
+    );
+    assert_eq!(
+        eval("[1, 3) & [1, 3]").unwrap(),
+        interval("1", "3", false, true)
+    );
+}
+
+#[test]
+fn averages_take_the_mean_of_the_section() {
+    assert_eq!(subtotals("2\n4\n6\n8\n~~~\n\n"), [num("5")]);
+    assert_eq!(subtotals("[1, 3]\n5\n~~~\n\n"), [closed("3", "4")]);
+}
+
+#[test]
+fn undefined_results_are_errors() {
+    for (src, form) in [
+        ("0 / 0", "0 / 0"),
+        ("0 // 0", "0 // 0"),
+        ("0 % 0", "0 % 0"),
+        ("[inf, inf] - [inf, inf]", "inf - inf"),
+        ("[-inf, -inf] + [inf, inf]", "inf - inf"),
+    ] {
+        assert!(
+            matches!(eval(src), Err(EvalError::Undefined { form: f, .. }) if f == form),
+            "{src}"
+        );
+    }
+}
+
+#[test]
+fn undefined_results_point_at_their_operands() {
+    for (src, left, right) in [
+        ("1 + 0 / 0\n", 4..5, 8..9),
+        ("[inf, inf] - [inf, inf]\n", 0..10, 13..23),
+    ] {
+        let mut lines = parse_document(src).unwrap();
+        let (e, _) = evaluate(&mut lines).unwrap_err();
+        let EvalError::Undefined {
+            left: Some(l),
+            right: Some(r),
+            ..
+        } = e
+        else {
+            panic!("{e:?}")
+        };
+        assert_eq!((l.into_range(), r.into_range()), (left, right), "{src}");
+    }
+}
+
+#[test]
+fn rounding_functions_take_an_optional_precision() {
+    assert_eq!(eval("round(2.5)").unwrap(), num("3"));
+    assert_eq!(eval("round(-2.5)").unwrap(), num("-3"));
+    assert_eq!(eval("round(2.345, 2)").unwrap(), num("2.35"));
+    assert_eq!(eval("floor(2.7)").unwrap(), num("2"));
+    assert_eq!(eval("floor(-2.7)").unwrap(), num("-3"));
+    assert_eq!(eval("floor(2.789, 1)").unwrap(), num("2.7"));
+    assert_eq!(eval("ceil(2.1)").unwrap(), num("3"));
+    assert_eq!(eval("ceil(2.123, 2)").unwrap(), num("2.13"));
+    assert_eq!(eval("round([1.2, 3.4])").unwrap(), closed("1", "4"));
+    assert!(matches!(
+        eval("round(1, 0.5)"),
+        Err(EvalError::InvalidPrecision)
+    ));
+}
+
+#[test]
+fn hulls_cover_the_section() {
+    assert_eq!(subtotals("1\n[2, 5]\n-1\n|||\n\n"), [closed("-1", "5")]);
+}
+
+// Whether the first subtotal of `src` came out within its target.
+fn on_target(src: &str) -> Option<bool> {
+    let mut lines = parse_document(src).unwrap();
+    evaluate(&mut lines).unwrap();
+    lines.iter().find_map(|line| match line {
+        Line::Subtotal { on_target, .. } => *on_target,
+        _ => None,
+    })
+}
+
+#[test]
+fn targets_include_their_bounds() {
+    assert_eq!(on_target("10\n4\n--- 6.5 ± 0.5\n\n"), Some(true));
+    assert_eq!(on_target("10\n4\n--- 5.5 ± 0.5\n\n"), Some(true));
+    assert_eq!(on_target("10\n4\n--- 6.51 ± 0.5\n\n"), Some(false));
+    assert_eq!(on_target("10\n4\n--- 5.49 ± 0.5\n\n"), Some(false));
+    assert_eq!(on_target("10\n4\n---\n\n"), None);
+}
+
+#[test]
+fn iterating_agrees_with_evaluating() {
+    let src = "# rent\n10\n4\n---\n\n\nx = @ * 2\n";
+    let lines: Vec<_> = evaluate_iter(src).unwrap().map(Result::unwrap).collect();
+    let kinds: Vec<_> = lines.iter().map(|line| line.kind()).collect();
+    assert_eq!(
+        kinds,
+        [
+            LineKind::Comment,
+            LineKind::Operation,
+            LineKind::Operation,
+            LineKind::Subtotal(SubtotalKind::Total),
+            LineKind::Blank,
+            LineKind::Assign
+        ]
+    );
+    assert_eq!(lines[3].value(), Some(num("6")));
+    assert_eq!(lines[0].comment(), "# rent");
+    let lines: Vec<_> = lines.into_iter().map(|line| line.line).collect();
+    assert_eq!(lines, evaluated(parse_document(src).unwrap()).unwrap());
+}
+
+#[test]
+fn points_collapse_intervals_to_their_midpoint() {
+    assert_eq!(eval("point([2, 4])").unwrap(), num("3"));
+    assert_eq!(eval("point(5)").unwrap(), num("5"));
+}
+
+#[test]
+fn labels_stand_for_their_subtotals() {
+    assert_eq!(
+        values("100\n20\n--- groceries:\n\ngroceries * 12\n"),
+        [num("100"), num("20"), num("960")]
+    );
+    assert!(parse_document("groceries * 12\n1\n--- groceries:\n\n").is_err());
+    // Labels of subtotals without a value are left undefined.
+    assert!(matches!(
+        eval("--- groceries:\n\ngroceries * 12\n"),
+        Err(EvalError::UndefinedVariable(name)) if name == "groceries"
+    ));
+}
+
+#[test]
+fn squares_are_tighter_than_products() {
+    assert_eq!(eval("sq([-2, 3])").unwrap(), closed("0", "9"));
+    assert_eq!(eval("[-2, 3] * [-2, 3]").unwrap(), closed("-6", "9"));
+    assert_eq!(eval("sq(-3)").unwrap(), num("9"));
+}
+
+#[test]
+fn operations_continue_on_lines_starting_with_an_operator() {
+    let lines = evaluated(parse_document("1200\n  * 12 per year\n").unwrap()).unwrap();
+    match &lines[..] {
+        [Line::Operation {
+            value: Some(v),
+            comment,
+            ..
+        }] => {
+            assert_eq!(*v, num("14400"));
+            assert_eq!(comment, "per year");
+        }
+        lines => panic!("{lines:?}"),
+    }
+    assert_eq!(values("10\n/ 4\n"), [num("2.5")]);
+    assert!(parse_document("1200 monthly\n  * 12\n").is_err());
+}
+#[test]
+fn exponents_are_whole_numbers_within_range() {
+    assert_eq!(eval("2 ^ -1").unwrap(), num("0.5"));
+    assert!(matches!(
+        eval("2 ^ 0.5"),
+        Err(EvalError::NonIntegerExponent)
+    ));
+    let src = format!("2 ^ {}", i64::MIN);
+    assert!(matches!(eval(&src), Err(EvalError::Overflow)));
+}
+
+#[test]
+fn failing_operations_are_errors_rather_than_panics() {
+    assert!(matches!(eval("sqrt(-1)"), Err(EvalError::NegativeSqrt)));
+    assert!(matches!(
+        eval("79228162514264337593543950335 * 2"),
+        Err(EvalError::Overflow)
+    ));
+    assert!(matches!(eval("mid([1, inf])"), Err(EvalError::Unbounded)));
+    assert!(matches!(
+        eval("round(1, 0.5)"),
+        Err(EvalError::InvalidPrecision)
+    ));
+    assert!(matches!(eval("(1 < 2) + 1"), Err(EvalError::NotANumber)));
+}
+
+#[test]
+fn evaluation_errors_point_at_their_operation() {
+    let src = "1 + 6 / 0\n";
+    let mut lines = parse_document(src).unwrap();
+    let (e, span) = evaluate(&mut lines).unwrap_err();
+    assert!(matches!(e, EvalError::DivisionByZero));
+    assert_eq!(&src[span.into_range()], "6 / 0");
+}
+
+#[test]
+fn checks_can_allow_for_rounding() {
+    let mut lines = parse_document("10 / 3\n---\n  3.33\n").unwrap();
+    evaluate(&mut lines).unwrap();
+    assert_eq!(check(&lines).len(), 1);
+    assert!(check_within(&lines, Decimal::new(1, 2)).is_empty());
+    assert_eq!(check_within(&lines, Decimal::new(1, 3)).len(), 1);
+}
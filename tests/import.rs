@@ -0,0 +1,35 @@
+use calc::{evaluate, import_csv, Line, Value};
+
+fn subtotal(lines: &[Line]) -> Option<Value> {
+    lines.iter().find_map(|line| match line {
+        Line::Subtotal { value, .. } => *value,
+        _ => None,
+    })
+}
+
+#[test]
+fn a_column_imports_as_a_document() {
+    let csv = "name,amount\nrent,1000\nfood,250.5\nfun,49.5\n";
+    let mut lines = import_csv(csv, "amount").unwrap();
+    evaluate(&mut lines).unwrap();
+    // The first row starts the running total, and the others are subtracted from it.
+    assert_eq!(subtotal(&lines), Some(Value::Number(700.into())));
+}
+
+#[test]
+fn every_bad_row_is_reported() {
+    let csv = "name,amount\nrent,1000\nfood\nfun,lots\nmisc,\"1,5\"\n";
+    let errors: Vec<String> = import_csv(csv, "amount")
+        .unwrap_err()
+        .iter()
+        .map(|(e, _)| e.to_string())
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            "row 3 has no cell in the column",
+            "row 4: `lots` is not a number",
+            "row 5: `1,5` is not a number",
+        ]
+    );
+}
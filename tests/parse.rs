@@ -0,0 +1,222 @@
+use std::borrow::Cow;
+
+use calc::{parse_document, Bound, Line, Openness, Operation, Value};
+use chumsky::span::SimpleSpan;
+use rust_decimal::Decimal;
+
+fn dec(s: &str) -> Decimal {
+    s.parse().unwrap()
+}
+
+fn interval(a: &str, b: &str, lower: bool, upper: bool) -> Value {
+    let (a, b) = (Bound::Finite(dec(a)), Bound::Finite(dec(b)));
+    Value::Interval(a, b, Openness { lower, upper })
+}
+
+// The value making up a one-line document.
+fn value(src: &str) -> Value {
+    match &parse_document(src).unwrap()[..] {
+        [Line::Operation {
+            operation: Operation::Value(v, _),
+            ..
+        }] => *v,
+        lines => panic!("{lines:?}"),
+    }
+}
+
+#[test]
+fn numbers_take_an_exponent() {
+    assert!(matches!(value("1.5e3"), Value::Number(n) if n == dec("1500")));
+    assert!(matches!(value("2E-2"), Value::Number(n) if n == dec("0.02")));
+    assert!(parse_document("1.5e\n").is_err());
+}
+
+#[test]
+fn digits_may_be_grouped_with_underscores() {
+    assert!(matches!(value("1_000.50"), Value::Number(n) if n == dec("1000.50")));
+    assert!(matches!(value("1_000_000"), Value::Number(n) if n == dec("1000000")));
+}
+
+#[test]
+fn numbers_too_long_for_a_decimal_are_errors() {
+    let errs = parse_document("9999999999999999999999999999999999999999\n").unwrap_err();
+    assert!(!errs.is_empty());
+}
+
+#[test]
+fn uncertainties_too_large_for_a_decimal_are_errors() {
+    assert!(parse_document("79228162514264337593543950335 ± 1\n").is_err());
+}
+
+#[test]
+fn dangling_operators_expect_a_value() {
+    for op in ['*', '/'] {
+        let src = format!("3 {op}\n");
+        let errs = parse_document(&src).unwrap_err();
+        let expected = format!("expected value after `{op}`");
+        assert!(
+            errs.iter().any(|e| e.to_string().contains(&expected)),
+            "{errs:?}"
+        );
+    }
+}
+
+// The comments of `src`, whether on lines of their own or after values.
+fn comments(src: &str) -> Vec<String> {
+    parse_document(src)
+        .unwrap()
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Operation { comment, .. }
+            | Line::Subtotal { comment, .. }
+            | Line::Comment(comment, _) => Some(comment.into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn comments_end_before_carriage_returns() {
+    assert_eq!(
+        comments("# note\r\n10 rent\r\n4 food\r\n---\r\n6 left\r\n"),
+        ["# note", "rent", "food", "left"]
+    );
+}
+
+// The kind of each line of `src`, as a letter.
+fn kinds(src: &str) -> String {
+    parse_document(src)
+        .unwrap()
+        .iter()
+        .map(|line| match line {
+            Line::Operation { .. } => 'o',
+            Line::Subtotal { .. } => 's',
+            Line::Assign { .. } => 'a',
+            Line::Comment(..) => 'c',
+            Line::Blank(_) => 'b',
+        })
+        .collect()
+}
+
+#[test]
+fn blank_lines_space_out_sections() {
+    assert_eq!(kinds("10\n4\n---\n\n\n20\n\n5\n---\n\n"), "oosbobos");
+    assert_eq!(kinds("1\n  \n2\n"), "obo");
+    assert_eq!(kinds("1\n\n\n"), "o");
+}
+
+#[test]
+fn tabs_separate_like_spaces() {
+    let src = "10\trent\n\t4 * 2\tfood\n---\n2\tleft\n";
+    assert_eq!(comments(src), ["rent", "food", "left"]);
+    let lines = parse_document(src).unwrap();
+    assert!(matches!(
+        &lines[2],
+        Line::Subtotal { written: Some(v), .. } if *v == Value::Number(dec("2"))
+    ));
+}
+
+#[test]
+fn intervals_stay_on_one_line() {
+    assert!(parse_document("[1,\n2]\n").is_err());
+}
+
+#[test]
+fn total_keywords_stand_for_rules() {
+    let parts = |src: &str| match &parse_document(src).unwrap()[2] {
+        Line::Subtotal {
+            written, comment, ..
+        } => (*written, comment.to_string()),
+        line => panic!("{line:?}"),
+    };
+    assert_eq!(
+        parts("10\n4\ntotal: 6 left\n"),
+        parts("10\n4\n---\n6 left\n")
+    );
+    assert_eq!(parts("10\n4\ntotal:\n"), parts("10\n4\n---\n\n"));
+}
+
+#[test]
+fn plus_signs_mean_nothing() {
+    assert_eq!(value("+12.50"), value("12.50"));
+    assert!(parse_document("+-5\n").is_err());
+}
+
+#[test]
+fn reversed_intervals_keep_their_brackets() {
+    assert_eq!(value("(5, 1]"), interval("1", "5", false, true));
+    assert_eq!(value("[5, 1)"), interval("1", "5", true, false));
+    assert_eq!(value("(5, 1)"), interval("1", "5", true, true));
+    assert_eq!(value("[5, 1]"), interval("1", "5", false, false));
+}
+
+#[test]
+fn comments_borrow_from_the_source() {
+    let lines = parse_document("# note\n10 rent\n---\n10 left\n").unwrap();
+    for line in &lines {
+        if let Line::Operation { comment, .. }
+        | Line::Subtotal { comment, .. }
+        | Line::Comment(comment, _) = line
+        {
+            assert!(matches!(comment, Cow::Borrowed(_)), "{line:?}");
+        }
+    }
+    let owned: Vec<Line<'static>> = lines.into_iter().map(Line::into_owned).collect();
+    assert!(matches!(&owned[0], Line::Comment(Cow::Owned(c), _) if c == "# note"));
+}
+
+// The label and comment of the subtotals in `src`.
+fn subtotals(src: &str) -> Vec<(Option<String>, String)> {
+    parse_document(src)
+        .unwrap()
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Subtotal { label, comment, .. } => Some((label, comment.into_owned())),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn subtotals_take_a_label_and_a_comment() {
+    assert_eq!(
+        subtotals("1\n--- subtotal1: Groceries\n"),
+        [(Some("subtotal1".to_string()), "Groceries".to_string())]
+    );
+    assert_eq!(
+        subtotals("1\n--- subtotal1:\n1 Groceries\n"),
+        [(Some("subtotal1".to_string()), "Groceries".to_string())]
+    );
+    assert_eq!(
+        subtotals("1\n--- subtotal1 Groceries\n"),
+        [(None, "subtotal1 Groceries".to_string())]
+    );
+}
+
+#[test]
+fn operations_span_their_operands() {
+    let lines = parse_document("1 + 23 * 4\n").unwrap();
+    let Line::Operation {
+        operation: Operation::Add(l, r, span),
+        ..
+    } = &lines[0]
+    else {
+        panic!("{lines:?}")
+    };
+    assert_eq!(l.span(), Some(SimpleSpan::from(0..1)));
+    assert_eq!(r.span(), Some(SimpleSpan::from(4..10)));
+    assert_eq!(*span, SimpleSpan::from(0..10));
+}
+
+#[test]
+fn words_after_a_rule_are_a_comment_leaving_the_next_line_alone() {
+    let lines = parse_document("10\n--- Groceries\n5 rent\n").unwrap();
+    assert!(
+        matches!(&lines[1], Line::Subtotal { label: None, comment, .. } if comment == "Groceries")
+    );
+    assert!(matches!(&lines[2], Line::Operation { comment, .. } if comment == "rent"));
+    assert_eq!(
+        subtotals("1\n--- Net total\n"),
+        subtotals("1\n---   Net total  \n")
+    );
+}
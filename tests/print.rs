@@ -0,0 +1,408 @@
+use std::fmt::{self, Write};
+
+use calc::{
+    evaluate, evaluate_with, evaluated, parse_document, parse_document_with, pretty_print, Align,
+    CommentOverflow, EvalOptions, Format, FormatOptions, Line, Locale, Value,
+};
+
+use rust_decimal::Decimal;
+
+fn render(src: &str, opts: &FormatOptions) -> String {
+    let mut lines = parse_document(src).unwrap();
+    evaluate(&mut lines).unwrap();
+    pretty_print(lines, opts).unwrap()
+}
+
+// The expression of a one-line document, as printed back.
+fn expression(src: &str) -> String {
+    render(src, &FormatOptions::default())
+        .trim_end()
+        .to_string()
+}
+
+#[test]
+fn values_are_rounded_to_the_precision() {
+    let opts = FormatOptions::builder().precision(4).build();
+    assert_eq!(render("1.23456\n", &opts).trim_end(), "1.2346");
+    assert_eq!(expression("1.23456"), "1.23");
+}
+
+#[test]
+fn intervals_can_be_rounded_outward() {
+    let outward = FormatOptions::builder()
+        .precision(0)
+        .round_outward(true)
+        .build();
+    assert_eq!(render("[1.001, 1.999]\n", &outward).trim_end(), "[1, 2]");
+    assert_eq!(render("[1.2, 1.3]\n", &outward).trim_end(), "[1, 2]");
+    let nearest = FormatOptions::builder().precision(0).build();
+    assert_eq!(render("[1.2, 1.3]\n", &nearest).trim_end(), "[1, 1]");
+}
+
+#[test]
+fn documents_render_as_csv() {
+    let opts = FormatOptions::builder().format(Format::Csv).build();
+    let out = render("10 rent\n2.5 * 2 fun, mostly\n[1, 2]\n---\n\n", &opts);
+    assert_eq!(
+        out,
+        "kind,expression,value,comment\n\
+         operation,10,10,rent\n\
+         operation,2.5 * 2,5,\"fun, mostly\"\n\
+         operation,\"[1, 2]\",\"[1, 2]\",\n\
+         subtotal,,\"[3, 4]\",\n"
+    );
+}
+
+#[test]
+fn documents_render_as_json() {
+    let opts = FormatOptions::builder().format(Format::Json).build();
+    assert_eq!(
+        render("10 rent\n4 food\n", &opts),
+        "[\n  \
+         {\"type\": \"operation\", \"value\": \"10\", \"comment\": \"rent\"},\n  \
+         {\"type\": \"operation\", \"value\": \"4\", \"comment\": \"food\"}\n\
+         ]\n"
+    );
+}
+
+// The lines of a rendered document, without the padding at their ends.
+fn trimmed(out: &str) -> Vec<&str> {
+    out.lines().map(str::trim_end).collect()
+}
+
+#[test]
+fn decimal_points_line_up() {
+    let out = render("1.5\n10.25\n100\n", &FormatOptions::default());
+    assert_eq!(trimmed(&out), ["  1.5", " 10.25", "100"]);
+}
+
+#[test]
+fn digits_are_grouped_in_thousands() {
+    let opts = FormatOptions::builder().digit_separator(',').build();
+    let grouped = |src: &str| render(src, &opts).trim().to_string();
+    assert_eq!(grouped("-1234567.5\n"), "-1,234,567.5");
+    assert_eq!(grouped("1000\n"), "1,000");
+    assert_eq!(grouped("999\n"), "999");
+    assert_eq!(grouped("-12\n"), "-12");
+    assert_eq!(grouped("0.5\n"), "0.5");
+}
+
+#[test]
+fn calls_print_back() {
+    assert_eq!(expression("mid([1, 3])"), "mid([1, 3])");
+    assert_eq!(expression("radius([1, 3]) * 2"), "radius([1, 3]) * 2");
+}
+
+#[test]
+fn documents_render_as_markdown() {
+    let opts = FormatOptions::builder().format(Format::Markdown).build();
+    assert_eq!(
+        render("10 rent\n4 food\n---\n\n", &opts),
+        "| Expression | Value | Comment |\n\
+         | ---: | ---: | --- |\n\
+         | 10 | 10 | rent |\n\
+         | 4 | 4 | food |\n\
+         |  | 6 |  |\n\
+         | --- | --- | --- |\n"
+    );
+}
+
+fn subtotals(src: &str) -> Vec<Option<Value>> {
+    let mut lines = parse_document(src).unwrap();
+    evaluate(&mut lines).unwrap();
+    lines
+        .into_iter()
+        .filter_map(|line| match line {
+            Line::Subtotal { value, .. } => Some(value),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn rules_use_the_rule_char_and_read_back() {
+    let src = "10\n3\n---\n\n";
+    let opts = FormatOptions::builder().rule_char('=').build();
+    let out = render(src, &opts);
+    assert!(out.lines().any(|line| line == "=="), "{out}");
+    assert_eq!(subtotals(&out), subtotals(src));
+}
+
+#[test]
+#[should_panic(expected = "rule char must be `-` or `=`")]
+fn rule_chars_other_than_total_rules_are_rejected() {
+    FormatOptions::builder().rule_char('~');
+}
+
+#[test]
+fn values_display_as_they_print() {
+    let v = Value::interval(Decimal::ONE, Decimal::new(25, 1));
+    assert_eq!(v.to_string(), "[1, 2.5]");
+    assert_eq!(v.to_string(), expression("[1, 2.5]"));
+    assert_eq!(Value::Number(Decimal::new(-1234, 3)).to_string(), "-1.23");
+    assert_eq!(
+        format!("{:.3}", Value::Number(Decimal::new(-1234, 3))),
+        "-1.234"
+    );
+}
+
+// The second column of each line of a rendered document.
+fn second_column(out: &str) -> Vec<&str> {
+    out.lines()
+        .map(|line| line.split_whitespace().nth(1).unwrap_or(""))
+        .collect()
+}
+
+#[test]
+fn running_totals_follow_each_operation() {
+    let opts = FormatOptions::builder().running(true).build();
+    let out = render("10\n3\n2\n", &opts);
+    assert_eq!(second_column(&out), ["10", "7", "5"], "{out}");
+}
+
+#[test]
+fn percentages_are_of_the_given_total() {
+    let opts = FormatOptions::builder()
+        .percent_of(Value::Number(Decimal::new(200, 0)))
+        .build();
+    let out = render("50 a\n100 b\n50 c\n", &opts);
+    assert_eq!(second_column(&out), ["25%", "50%", "25%"], "{out}");
+}
+
+#[test]
+fn relative_widths_are_against_the_midpoint() {
+    let opts = FormatOptions::builder().rel_width(true).build();
+    assert!(render("[9, 11]\n", &opts).contains("(±10.0%)"));
+    assert!(render("[-1, 1]\n", &opts).contains("(n/a)"));
+    assert!(render("[1, inf]\n", &opts).contains("(n/a)"));
+}
+
+#[test]
+fn built_options_all_apply() {
+    let opts = FormatOptions::builder()
+        .precision(1)
+        .digit_separator(',')
+        .rule_char('=')
+        .indent(2)
+        .build();
+    let out = render("1234.56\n1000\n---\n\n", &opts);
+    assert_eq!(
+        trimmed(&out),
+        ["  1,234.6", "  1,000", "  =======", "    234.6"]
+    );
+}
+
+#[test]
+fn long_comments_wrap_or_get_cut_short() {
+    let src = "1 a fairly long comment here\n";
+    let wrap = FormatOptions::builder()
+        .comment_width(10, CommentOverflow::Wrap)
+        .build();
+    assert_eq!(
+        render(src, &wrap),
+        "1 a fairly\n  long\n  comment\n  here\n"
+    );
+    let truncate = FormatOptions::builder()
+        .comment_width(10, CommentOverflow::Truncate)
+        .build();
+    assert_eq!(render(src, &truncate), "1 a fairly …\n");
+    assert_eq!(render("1 short\n", &truncate), "1 short\n");
+}
+
+#[test]
+fn documents_render_as_ledger_entries() {
+    let opts = FormatOptions::builder().format(Format::Ledger).build();
+    assert_eq!(
+        render("10 rent\n4 food\n---\n6 left\n", &opts),
+        "1970-01-01 rent\n    calc  10\n    equity\n\n\
+         1970-01-01 food\n    calc  -4\n    equity\n\n\
+         1970-01-01 left\n    calc  0 = 6\n\n"
+    );
+}
+
+// A writer that never has room for anything.
+struct Full;
+
+impl fmt::Write for Full {
+    fn write_str(&mut self, _: &str) -> fmt::Result {
+        Err(fmt::Error)
+    }
+}
+
+#[test]
+fn formatting_errors_are_returned() {
+    let v = Value::interval(Decimal::ONE, Decimal::TWO);
+    assert!(write!(Full, "{v}").is_err());
+    let opts = FormatOptions::builder().format(Format::Ledger).build();
+    let lines = evaluated(parse_document("[1, 2]\n").unwrap()).unwrap();
+    assert!(pretty_print(lines, &opts).is_err());
+}
+
+#[test]
+fn only_repeated_associative_operations_lose_their_parentheses() {
+    assert_eq!(expression("2 * (7 // 2)"), "2 * (7 // 2)");
+    assert_eq!(expression("2 * (7 % 2)"), "2 * (7 % 2)");
+    assert_eq!(expression("2 * (7 / 2)"), "2 * (7 / 2)");
+    assert_eq!(expression("2 * (3 * 4)"), "2 * 3 * 4");
+    assert_eq!(expression("2 + (3 + 4)"), "2 + 3 + 4");
+}
+
+#[test]
+fn brackets_print_back() {
+    for src in ["[1, 2]", "[1, 2)", "(1, 2]", "(1, 2)"] {
+        assert_eq!(expression(src), src);
+    }
+}
+
+#[test]
+fn indents_shift_whole_lines() {
+    let src = "10 rent\n4\n---\n\n\n# note\n";
+    let plain = render(src, &FormatOptions::default());
+    let indented = render(src, &FormatOptions::builder().indent(4).build());
+    let expected: Vec<_> = plain
+        .lines()
+        .map(|line| match line {
+            "" => String::new(),
+            line => format!("    {line}"),
+        })
+        .collect();
+    assert_eq!(indented.lines().collect::<Vec<_>>(), expected);
+}
+
+#[test]
+fn comma_locales_read_and_write_decimal_commas() {
+    let lines = parse_document_with("1.234,5\n0,25\n[1,5, 2]\n", Locale::Comma).unwrap();
+    let opts = FormatOptions::builder()
+        .locale(Locale::Comma)
+        .digit_separator('.')
+        .build();
+    let out = pretty_print(evaluated(lines).unwrap(), &opts).unwrap();
+    assert_eq!(trimmed(&out), ["  1.234,5", "      0,25", "[1,5, 2]"]);
+}
+
+// The values and comments of a rendered document, leaving out rules.
+fn words(out: &str) -> Vec<&str> {
+    out.lines()
+        .filter(|line| !line.chars().all(|c| c == '-'))
+        .flat_map(str::split_whitespace)
+        .collect()
+}
+
+#[test]
+fn reconciled_values_add_up_to_their_total() {
+    let sum = EvalOptions {
+        sum: true,
+        ..EvalOptions::default()
+    };
+    let render_reconciled = |reconcile| {
+        let mut lines = parse_document("0.333\n0.333\n0.334\n---\n\n").unwrap();
+        evaluate_with(&mut lines, &sum).unwrap();
+        let opts = FormatOptions::builder().reconcile(reconcile).build();
+        pretty_print(lines, &opts).unwrap()
+    };
+    assert_eq!(
+        words(&render_reconciled(false)),
+        ["0.33", "0.33", "0.33", "1"]
+    );
+    assert_eq!(
+        words(&render_reconciled(true)),
+        ["0.33", "0.33", "0.34", "1"]
+    );
+}
+
+#[test]
+#[should_panic(expected = "precision must be at most 28")]
+fn precisions_beyond_a_decimal_are_rejected() {
+    FormatOptions::builder().precision(29);
+}
+
+// How each operation entered the running total, as shown with `show_op`.
+fn shown_ops(src: &str, opts: &EvalOptions) -> String {
+    let mut lines = parse_document(src).unwrap();
+    evaluate_with(&mut lines, opts).unwrap();
+    let out = pretty_print(lines, &FormatOptions::builder().show_op(true).build()).unwrap();
+    out.lines()
+        .filter(|line| line.chars().nth(1) == Some(' '))
+        .filter_map(|line| line.chars().next().filter(|c| "=+-".contains(*c)))
+        .collect()
+}
+
+#[test]
+fn operations_show_how_the_evaluator_took_them() {
+    let sections = EvalOptions {
+        sections: true,
+        ..EvalOptions::default()
+    };
+    let sum = EvalOptions {
+        sum: true,
+        ..EvalOptions::default()
+    };
+    assert_eq!(shown_ops("10\n4\n", &EvalOptions::default()), "=-");
+    assert_eq!(shown_ops("10\n---\n\n5\n", &sections), "==");
+    assert_eq!(shown_ops("5\n0\n", &sum), "=+");
+}
+
+#[test]
+fn byte_order_marks_are_skipped() {
+    let src = "10 rent\n4\n---\n6\n";
+    let opts = FormatOptions::default();
+    assert_eq!(render(&format!("\u{FEFF}{src}"), &opts), render(src, &opts));
+}
+
+#[test]
+fn documents_render_as_yaml() {
+    let opts = FormatOptions::builder().format(Format::Yaml).build();
+    assert_eq!(
+        render("10 rent\n4 food\n", &opts),
+        "- type: operation\n  value: \"10\"\n  comment: \"rent\"\n\
+         - type: operation\n  value: \"4\"\n  comment: \"food\"\n"
+    );
+}
+
+#[test]
+fn comments_can_be_left_out() {
+    let src = "# note\n10 rent\n4 food\n---\n6 left\n";
+    let with = render(src, &FormatOptions::default());
+    assert!(with.contains("# note") && with.contains("rent"), "{with}");
+    let without = render(src, &FormatOptions::builder().comments(false).build());
+    assert_eq!(without, "10\n 4\n--\n 6\n");
+}
+
+#[test]
+fn values_align_as_asked() {
+    let aligned = |align| {
+        render(
+            "1.5\n10.25\n",
+            &FormatOptions::builder().align(align).build(),
+        )
+    };
+    assert_eq!(trimmed(&aligned(Align::Decimal)), [" 1.5", "10.25"]);
+    assert_eq!(trimmed(&aligned(Align::Right)), ["  1.5", "10.25"]);
+    assert_eq!(trimmed(&aligned(Align::Left)), ["1.5", "10.25"]);
+}
+
+#[test]
+fn labels_read_back_as_labels() {
+    let out = render("10\n--- groceries:\n\n", &FormatOptions::default());
+    let lines = parse_document(&out).unwrap();
+    assert!(
+        matches!(&lines[1], Line::Subtotal { label: Some(l), .. } if l == "groceries"),
+        "{out}"
+    );
+}
+
+#[test]
+fn negative_zeros_print_without_their_sign() {
+    let out = render("0.001\n0.002\n---\n\n", &FormatOptions::default());
+    assert_eq!(trimmed(&out), ["0", "0", "-", "0"]);
+    assert_eq!(expression("[-0.001, 1]"), "[0, 1]");
+    assert_eq!(Value::Number(Decimal::new(-1, 3)).to_string(), "0");
+}
+
+#[test]
+fn indented_comments_print_back_indented() {
+    let out = render("    # food\n10\n", &FormatOptions::default());
+    assert_eq!(out, "    # food\n10\n");
+    let opts = FormatOptions::builder().format(Format::Ledger).build();
+    assert_eq!(render("  # food\n", &opts), "; food\n");
+}
@@ -0,0 +1,29 @@
+#![cfg(feature = "serde")]
+
+use calc::{evaluate, parse_document, Line};
+
+const DOCUMENT: &str = "# groceries
+rate = 7%
+1_000.50   rent
+[10, 50]   food
+2 * rate + -(3 - 2)
+---
+950.40 left over
+";
+
+#[test]
+fn parsed_documents_round_trip_through_json() {
+    let lines = parse_document(DOCUMENT).unwrap();
+    let json = serde_json::to_string(&lines).unwrap();
+    let back: Vec<Line> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, lines);
+}
+
+#[test]
+fn evaluated_documents_round_trip_through_json() {
+    let mut lines = parse_document(DOCUMENT).unwrap();
+    evaluate(&mut lines).unwrap();
+    let json = serde_json::to_string(&lines).unwrap();
+    let back: Vec<Line> = serde_json::from_str(&json).unwrap();
+    assert_eq!(back, lines);
+}
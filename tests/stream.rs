@@ -0,0 +1,51 @@
+// Kept apart from the other command line tests so that the memory used by their runs of the binary
+// doesn't get counted here.
+#![cfg(target_os = "linux")]
+
+use std::process::Command;
+
+// A document of `sections` identical sections, each ending with its subtotal.
+fn document(sections: usize) -> String {
+    format!("100 rent\n{}---\n\n", "1.5 food\n".repeat(18)).repeat(sections)
+}
+
+// Runs the binary on `src`, written to a file so that it can be read while it's streamed.
+fn calc(args: &[&str], src: &str, name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("calc-{}-{name}", std::process::id()));
+    std::fs::write(&path, src).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_calc"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+// The most memory used by any of the runs of the binary so far, in kilobytes.
+fn max_rss() -> libc::c_long {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    assert_eq!(
+        unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) },
+        0
+    );
+    usage.ru_maxrss
+}
+
+#[test]
+fn streaming_uses_bounded_memory_and_matches_batch_output() {
+    let small = document(1_000);
+    let streamed = calc(&["--stream", "--sections"], &small, "small");
+    let before = max_rss();
+    // About 5 MB over 600,000 lines, which would take far more than that once parsed.
+    calc(&["--stream", "--sections"], &document(30_000), "large");
+    let after = max_rss();
+    assert!(after - before < 8 * 1024, "{before} kB, then {after} kB");
+
+    assert_eq!(streamed, calc(&["--sections"], &small, "batch"));
+}
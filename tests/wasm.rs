@@ -0,0 +1,14 @@
+#![cfg(feature = "wasm")]
+
+use calc::{run, FormatOptions};
+
+#[test]
+fn runs_give_the_output_or_the_errors() {
+    let opts = FormatOptions::default();
+    assert_eq!(run("10\n4\n---\n\n", &opts).unwrap(), "10 \n 4 \n--\n 6 \n");
+    let err = run("[1, 2\n", &opts).unwrap_err();
+    assert!(err.starts_with("Error:"), "{err}");
+    let err = run("1 + 6 / 0\n", &opts).unwrap_err();
+    assert!(err.contains("division by zero"), "{err}");
+    assert!(err.contains("in this expression"), "{err}");
+}